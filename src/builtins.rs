@@ -0,0 +1,237 @@
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::callable::NativeFunction;
+use crate::environment::Environment;
+use crate::symbol::Symbol;
+use crate::value::Value;
+
+const NATIVE_FUNCTIONS: &[NativeFunction] = &[
+    NativeFunction {
+        name: "ceil",
+        arity: 1,
+        function: ceil,
+    },
+    NativeFunction {
+        name: "clock",
+        arity: 0,
+        function: clock,
+    },
+    NativeFunction {
+        name: "floor",
+        arity: 1,
+        function: floor,
+    },
+    NativeFunction {
+        name: "len",
+        arity: 1,
+        function: len,
+    },
+    NativeFunction {
+        name: "round",
+        arity: 1,
+        function: round,
+    },
+    NativeFunction {
+        name: "substr",
+        arity: 3,
+        function: substr,
+    },
+    NativeFunction {
+        name: "type",
+        arity: 1,
+        function: type_name,
+    },
+];
+
+/// Define every native function in the given (global) environment
+pub fn define_builtins(environment: &mut Environment) {
+    for native in NATIVE_FUNCTIONS {
+        environment.define(
+            Symbol::intern(native.name),
+            Value::Callable(Rc::new(*native)),
+        );
+    }
+}
+
+/// Smallest whole number greater than or equal to a number
+fn ceil(arguments: &[Value]) -> Result<Value, String> {
+    let number = expect_number("ceil", &arguments[0])?;
+    Ok(Value::Number(number.ceil()))
+}
+
+/// Seconds since the unix epoch
+fn clock(_arguments: &[Value]) -> Result<Value, String> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| Value::Number(duration.as_secs_f64()))
+        .map_err(|e| format!("Failed to read the system clock: {e}"))
+}
+
+/// Largest whole number less than or equal to a number
+fn floor(arguments: &[Value]) -> Result<Value, String> {
+    let number = expect_number("floor", &arguments[0])?;
+    Ok(Value::Number(number.floor()))
+}
+
+/// Number of characters in a string
+fn len(arguments: &[Value]) -> Result<Value, String> {
+    let string = expect_string("len", &arguments[0])?;
+    Ok(Value::Number(string.chars().count() as f64))
+}
+
+/// Nearest whole number to a number, rounding halfway cases away from zero so that
+/// `round(2.5)` is `3` and `round(-2.5)` is `-3`
+fn round(arguments: &[Value]) -> Result<Value, String> {
+    let number = expect_number("round", &arguments[0])?;
+    Ok(Value::Number(number.round()))
+}
+
+/// Characters of a string from `start` up to but not including `end`
+fn substr(arguments: &[Value]) -> Result<Value, String> {
+    let string = expect_string("substr", &arguments[0])?;
+    let length = string.chars().count();
+    let start = expect_index("substr", &arguments[1], length)?;
+    let end = expect_index("substr", &arguments[2], length)?;
+
+    if start > end {
+        return Err(format!(
+            "substr() start {start} must not be after end {end}."
+        ));
+    }
+
+    Ok(Value::String(
+        string
+            .chars()
+            .skip(start)
+            .take(end - start)
+            .collect::<String>()
+            .into(),
+    ))
+}
+
+/// Name of the type of a value, such as `"number"` or `"instance"`
+fn type_name(arguments: &[Value]) -> Result<Value, String> {
+    Ok(Value::String(arguments[0].type_name().into()))
+}
+
+fn expect_number(name: &str, value: &Value) -> Result<f64, String> {
+    value
+        .as_number()
+        .ok_or_else(|| format!("{name}() expects a number, not '{value}'."))
+}
+
+fn expect_string<'a>(name: &str, value: &'a Value) -> Result<&'a str, String> {
+    match value {
+        Value::String(string) => Ok(string),
+        _ => Err(format!("{name}() expects a string, not '{value}'.")),
+    }
+}
+
+/// Check that `value` is a position within, or at the end of, a string of the given length
+fn expect_index(name: &str, value: &Value, length: usize) -> Result<usize, String> {
+    if !value.is_integer() {
+        return Err(format!("{name}() expects an integer index, not '{value}'."));
+    }
+
+    let index = value.as_number().unwrap_or_default();
+    if index < 0.0 || index > length as f64 {
+        return Err(format!(
+            "{name}() index {value} is out of range for a string of length {length}."
+        ));
+    }
+
+    Ok(index as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{errors_of, output_of, values_of};
+    use crate::Value;
+
+    #[test]
+    fn clock_returns_seconds_since_the_epoch() {
+        let values = values_of("clock();");
+        assert!(matches!(values[..], [Value::Number(now)] if now > 1e9));
+    }
+
+    #[test]
+    fn clock_takes_no_arguments() {
+        assert_eq!(errors_of("clock(1);"), ["Expected 0 arguments but got 1."]);
+    }
+
+    #[test]
+    fn measures_and_slices_strings_by_character() {
+        let source = r#"
+            print len("héllo");
+            print substr("hello", 1, 3);
+            print substr("abc", 0, 3);
+            print len(substr("abc", 3, 3));
+        "#;
+        assert_eq!(output_of(source), "5\nel\nabc\n0\n");
+    }
+
+    #[test]
+    fn rejects_bad_string_arguments() {
+        assert_eq!(errors_of("len(1);"), ["len() expects a string, not '1'."]);
+        assert_eq!(
+            errors_of(r#"substr("abc", 2, 1);"#),
+            ["substr() start 2 must not be after end 1."]
+        );
+        assert_eq!(
+            errors_of(r#"substr("abc", 0, 4);"#),
+            ["substr() index 4 is out of range for a string of length 3."]
+        );
+        assert_eq!(
+            errors_of(r#"substr("abc", 0.5, 1);"#),
+            ["substr() expects an integer index, not '0.5'."]
+        );
+    }
+
+    #[test]
+    fn names_the_type_of_each_value() {
+        let source = r#"
+            class A {}
+            fun f() {}
+            print type(1);
+            print type("s");
+            print type(true);
+            print type(nil);
+            print type(f);
+            print type(clock);
+            print type(A);
+            print type(A());
+            print type([1]);
+        "#;
+        assert_eq!(
+            output_of(source),
+            "number\nstring\nboolean\nnil\nfunction\nfunction\nclass\ninstance\nlist\n"
+        );
+    }
+
+    #[test]
+    fn rounds_negative_and_half_way_numbers() {
+        assert_eq!(
+            output_of(
+                "print floor(-2.5), ceil(-2.5), round(-2.5), round(2.5), round(-0.4), floor(3);"
+            ),
+            "-3 -2 -3 3 -0 3\n"
+        );
+    }
+
+    #[test]
+    fn rounds_only_numbers() {
+        assert_eq!(
+            errors_of(r#"floor("a");"#),
+            ["floor() expects a number, not 'a'."]
+        );
+        assert_eq!(
+            errors_of("ceil(true);"),
+            ["ceil() expects a number, not 'true'."]
+        );
+        assert_eq!(
+            errors_of("round(nil);"),
+            ["round() expects a number, not 'nil'."]
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use crate::symbol::Symbol;
+use crate::token::{Span, Token};
+use crate::value::Value;
+
+/// A single operation of the virtual machine. Operands are popped from the value stack and
+/// results are pushed back onto it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    /// Push the constant at this index of the chunk's constant table
+    Constant(usize),
+    Nil,
+    True,
+    False,
+    /// Discard the value on top of the stack
+    Pop,
+    /// Pop this many values and push a list of them, in the order they were pushed
+    BuildList(usize),
+
+    /// Pop a value and bind it to a global variable, replacing any previous binding
+    DefineGlobal(Symbol),
+    GetGlobal(Symbol),
+    /// Assign the value on top of the stack to an existing global variable, leaving it in place
+    SetGlobal(Symbol),
+    /// Push a copy of the local variable in this stack slot
+    GetLocal(usize),
+    /// Assign the value on top of the stack to the local variable in this stack slot, leaving it
+    /// in place
+    SetLocal(usize),
+
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Negate,
+    Not,
+
+    /// Continue from this instruction index
+    Jump(usize),
+    /// Continue from this instruction index if the value on top of the stack is falsey, without
+    /// popping it
+    JumpIfFalse(usize),
+    /// Continue from this instruction index if the value on top of the stack is truthy, without
+    /// popping it
+    JumpIfTrue(usize),
+
+    /// Pop this many values and print them on one line, separated by spaces
+    Print(usize),
+}
+
+/// Where in the source an instruction came from, used to locate runtime errors
+#[derive(Clone)]
+pub(crate) struct Origin {
+    /// The operator or name the instruction was compiled from
+    pub token: Token,
+    /// The whole expression the instruction evaluates, if errors should underline more than the
+    /// token
+    pub span: Option<Span>,
+}
+
+/// A compiled program, ready to be run by a [`Vm`](super::Vm)
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<Instruction>,
+    /// Values of literals too large to store in an instruction
+    pub constants: Vec<Value>,
+    /// Origin of each instruction that can fail, indexed in the same way as `code`
+    pub(crate) origins: Vec<Option<Origin>>,
+}
+
+impl Chunk {
+    /// Append an instruction, returning its index
+    pub(crate) fn push(&mut self, instruction: Instruction, origin: Option<Origin>) -> usize {
+        self.code.push(instruction);
+        self.origins.push(origin);
+        self.code.len() - 1
+    }
+
+    pub(crate) fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+}
@@ -0,0 +1,363 @@
+use std::rc::Rc;
+
+use crate::error::Diagnostic;
+use crate::expression::{self, Expression};
+use crate::statement::{self, FunctionDeclaration, Statement};
+use crate::symbol::Symbol;
+use crate::token::{Literal, Span, Token, TokenType};
+use crate::value::Value;
+
+use super::chunk::{Chunk, Instruction, Origin};
+
+/// A variable declared in a block, which lives in a slot of the VM's value stack rather than in
+/// the table of globals
+struct Local {
+    name: Symbol,
+    /// Number of blocks the variable is nested in
+    depth: usize,
+}
+
+/// Translates statements into a [`Chunk`] of instructions. As with the [`Resolver`], errors are
+/// collected rather than stopping compilation, so that several unsupported constructs can be
+/// reported at once.
+///
+/// [`Resolver`]: crate::resolver::Resolver
+pub struct Compiler {
+    chunk: Chunk,
+    /// Local variables currently in scope, in the order of their stack slots
+    locals: Vec<Local>,
+    scope_depth: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            chunk: Chunk::default(),
+            locals: Vec::new(),
+            scope_depth: 0,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn compile(mut self, statements: &[Statement]) -> Result<Chunk, Vec<Diagnostic>> {
+        for statement in statements {
+            statement.accept(&mut self);
+        }
+
+        if self.diagnostics.is_empty() {
+            Ok(self.chunk)
+        } else {
+            Err(self.diagnostics)
+        }
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.chunk.push(instruction, None)
+    }
+
+    /// Emit an instruction that can fail at runtime, reporting errors at `token`
+    fn emit_at(&mut self, instruction: Instruction, token: &Token) {
+        let origin = Origin {
+            token: token.clone(),
+            span: None,
+        };
+        self.chunk.push(instruction, Some(origin));
+    }
+
+    /// Emit the instruction for an operator, reporting errors over the whole of the expression
+    /// it applies to, in the same way as the interpreter
+    fn emit_operator(&mut self, instruction: Instruction, operator: &Token, span: Span) {
+        let origin = Origin {
+            token: operator.clone(),
+            span: Some(span),
+        };
+        self.chunk.push(instruction, Some(origin));
+    }
+
+    /// Point the jump instruction at index `jump` to the next instruction to be emitted
+    fn patch_jump(&mut self, jump: usize) {
+        let target = self.chunk.code.len();
+        match &mut self.chunk.code[jump] {
+            Instruction::Jump(to) | Instruction::JumpIfFalse(to) | Instruction::JumpIfTrue(to) => {
+                *to = target
+            }
+            instruction => unreachable!("Instruction {:?} is not a jump", instruction),
+        }
+    }
+
+    /// Stack slot of the innermost local variable called `name`, or `None` if it is a global
+    fn resolve_local(&self, name: &Token) -> Option<usize> {
+        self.locals
+            .iter()
+            .rposition(|local| local.name == name.symbol())
+    }
+
+    fn end_scope(&mut self) {
+        self.scope_depth -= 1;
+
+        // Discard the values of the variables declared in the block
+        while self
+            .locals
+            .last()
+            .is_some_and(|local| local.depth > self.scope_depth)
+        {
+            self.locals.pop();
+            self.emit(Instruction::Pop);
+        }
+    }
+
+    fn unsupported(&mut self, token: &Token, construct: &str) {
+        self.diagnostics.push(Diagnostic::compile(
+            token,
+            format!("{construct} are not supported by the bytecode compiler."),
+        ));
+    }
+}
+
+impl expression::Visitor<()> for Compiler {
+    fn visit_assign(&mut self, name: &Token, value: &Expression) {
+        value.accept(self);
+        match self.resolve_local(name) {
+            Some(slot) => {
+                self.emit(Instruction::SetLocal(slot));
+            }
+            None => self.emit_at(Instruction::SetGlobal(name.symbol()), name),
+        }
+    }
+
+    fn visit_binary(&mut self, left: &Expression, operator: &Token, right: &Expression) {
+        left.accept(self);
+        right.accept(self);
+
+        let instruction = match operator.token_type {
+            TokenType::Plus => Instruction::Add,
+            TokenType::Minus => Instruction::Subtract,
+            TokenType::Star => Instruction::Multiply,
+            TokenType::Slash => Instruction::Divide,
+            TokenType::Percent => Instruction::Modulo,
+            TokenType::StarStar => Instruction::Power,
+            TokenType::EqualEqual => Instruction::Equal,
+            TokenType::BangEqual => Instruction::NotEqual,
+            TokenType::Greater => Instruction::Greater,
+            TokenType::GreaterEqual => Instruction::GreaterEqual,
+            TokenType::Less => Instruction::Less,
+            TokenType::LessEqual => Instruction::LessEqual,
+            _ => unreachable!(
+                "Operator '{}' was not handled as a binary expression",
+                operator
+            ),
+        };
+        self.emit_operator(instruction, operator, left.span().to(right.span()));
+    }
+
+    fn visit_call(&mut self, _: &Expression, paren: &Token, _: &[Expression]) {
+        self.unsupported(paren, "Calls");
+    }
+
+    fn visit_comma(&mut self, expressions: &[Expression]) {
+        // Only the value of the last expression is kept
+        for (i, expression) in expressions.iter().enumerate() {
+            if i > 0 {
+                self.emit(Instruction::Pop);
+            }
+            expression.accept(self);
+        }
+    }
+
+    fn visit_get(&mut self, _: &Expression, name: &Token) {
+        self.unsupported(name, "Properties");
+    }
+
+    fn visit_grouping(&mut self, expression: &Expression) {
+        expression.accept(self);
+    }
+
+    fn visit_index(&mut self, _: &Expression, bracket: &Token, _: &Expression) {
+        self.unsupported(bracket, "Indexes");
+    }
+
+    fn visit_index_set(&mut self, _: &Expression, bracket: &Token, _: &Expression, _: &Expression) {
+        self.unsupported(bracket, "Indexes");
+    }
+
+    fn visit_lambda(&mut self, keyword: &Token, _: &FunctionDeclaration) {
+        self.unsupported(keyword, "Functions");
+    }
+
+    fn visit_list_literal(&mut self, elements: &[Expression]) {
+        for element in elements {
+            element.accept(self);
+        }
+        self.emit(Instruction::BuildList(elements.len()));
+    }
+
+    fn visit_literal(&mut self, value: &Literal) {
+        let instruction = match value {
+            Literal::String(str) => {
+                Instruction::Constant(self.chunk.add_constant(Value::String(str.as_str().into())))
+            }
+            Literal::Number(num) => {
+                Instruction::Constant(self.chunk.add_constant(Value::Number(*num)))
+            }
+            Literal::Boolean(true) => Instruction::True,
+            Literal::Boolean(false) => Instruction::False,
+            Literal::None => Instruction::Nil,
+        };
+        self.emit(instruction);
+    }
+
+    fn visit_logical(&mut self, left: &Expression, operator: &Token, right: &Expression) {
+        left.accept(self);
+
+        // Skip the right operand if the left one alone decides the result, keeping the left value
+        let jump = match operator.token_type {
+            TokenType::Or => self.emit(Instruction::JumpIfTrue(0)),
+            TokenType::And => self.emit(Instruction::JumpIfFalse(0)),
+            _ => unreachable!(
+                "Operator '{}' was not handled as a logical expression",
+                operator
+            ),
+        };
+        self.emit(Instruction::Pop);
+        right.accept(self);
+        self.patch_jump(jump);
+    }
+
+    fn visit_set(&mut self, _: &Expression, name: &Token, _: &Expression) {
+        self.unsupported(name, "Properties");
+    }
+
+    fn visit_super(&mut self, keyword: &Token, _: &Token) {
+        self.unsupported(keyword, "Classes");
+    }
+
+    fn visit_ternary(
+        &mut self,
+        condition: &Expression,
+        then_expr: &Expression,
+        else_expr: &Expression,
+    ) {
+        condition.accept(self);
+        let else_jump = self.emit(Instruction::JumpIfFalse(0));
+        self.emit(Instruction::Pop);
+        then_expr.accept(self);
+        let end_jump = self.emit(Instruction::Jump(0));
+
+        self.patch_jump(else_jump);
+        self.emit(Instruction::Pop);
+        else_expr.accept(self);
+        self.patch_jump(end_jump);
+    }
+
+    fn visit_this(&mut self, keyword: &Token) {
+        self.unsupported(keyword, "Classes");
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expression) {
+        right.accept(self);
+
+        let instruction = match operator.token_type {
+            TokenType::Bang => Instruction::Not,
+            TokenType::Minus => Instruction::Negate,
+            _ => unreachable!(
+                "Operator '{}' was not handled as a unary expression",
+                operator
+            ),
+        };
+        self.emit_operator(instruction, operator, operator.span().to(right.span()));
+    }
+
+    fn visit_variable(&mut self, name: &Token) {
+        match self.resolve_local(name) {
+            Some(slot) => {
+                self.emit(Instruction::GetLocal(slot));
+            }
+            None => self.emit_at(Instruction::GetGlobal(name.symbol()), name),
+        }
+    }
+}
+
+impl statement::Visitor<()> for Compiler {
+    fn visit_block(&mut self, statements: &[Statement]) {
+        self.scope_depth += 1;
+        for statement in statements {
+            statement.accept(self);
+        }
+        self.end_scope();
+    }
+
+    fn visit_class(&mut self, name: &Token, _: Option<&Expression>, _: &[Rc<FunctionDeclaration>]) {
+        self.unsupported(name, "Classes");
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        expression.accept(self);
+        self.emit(Instruction::Pop);
+    }
+
+    fn visit_function(&mut self, declaration: &Rc<FunctionDeclaration>) {
+        self.unsupported(declaration.declared_name(), "Functions");
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expression,
+        then_branch: &Statement,
+        else_branch: Option<&Statement>,
+    ) {
+        condition.accept(self);
+        let else_jump = self.emit(Instruction::JumpIfFalse(0));
+        self.emit(Instruction::Pop);
+        then_branch.accept(self);
+        let end_jump = self.emit(Instruction::Jump(0));
+
+        self.patch_jump(else_jump);
+        self.emit(Instruction::Pop);
+        if let Some(else_branch) = else_branch {
+            else_branch.accept(self);
+        }
+        self.patch_jump(end_jump);
+    }
+
+    fn visit_print(&mut self, _: &Token, expressions: &[Expression]) {
+        for expression in expressions {
+            expression.accept(self);
+        }
+        self.emit(Instruction::Print(expressions.len()));
+    }
+
+    fn visit_return(&mut self, keyword: &Token, _: Option<&Expression>) {
+        self.unsupported(keyword, "Functions");
+    }
+
+    fn visit_var(&mut self, name: &Token, initializer: Option<&Expression>) {
+        match initializer {
+            Some(expression) => expression.accept(self),
+            None => {
+                self.emit(Instruction::Nil);
+            }
+        }
+
+        // The initialiser is compiled before the variable is in scope, so that any use of the
+        // name inside it refers to an outer variable, as the resolver would otherwise reject it
+        if self.scope_depth == 0 {
+            self.emit(Instruction::DefineGlobal(name.symbol()));
+        } else {
+            self.locals.push(Local {
+                name: name.symbol(),
+                depth: self.scope_depth,
+            });
+        }
+    }
+
+    fn visit_while(&mut self, keyword: &Token, _: &Expression, _: &Statement) {
+        self.unsupported(keyword, "Loops");
+    }
+}
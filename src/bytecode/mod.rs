@@ -0,0 +1,80 @@
+//! An alternative backend that compiles statements into a flat list of instructions for a
+//! stack-based virtual machine, rather than walking the syntax tree every time it runs.
+//!
+//! Only part of the language is supported so far: literals, lists, operators, global and local
+//! variables, blocks, `print` and `if`. Compiling anything else, such as a function or a loop,
+//! reports an error. Operators are applied by the same code as in the tree-walking
+//! [`Interpreter`](crate::Interpreter), so the two backends agree on results and error messages.
+//!
+//! This module is only built with the `bytecode` cargo feature.
+
+mod chunk;
+mod compiler;
+mod vm;
+
+pub use chunk::{Chunk, Instruction};
+pub use compiler::Compiler;
+pub use vm::Vm;
+
+#[cfg(test)]
+mod tests {
+    use super::{Compiler, Vm};
+    use crate::error::Diagnostic;
+    use crate::parse_source;
+    use crate::test_util::output::SharedOutput;
+    use crate::test_util::{errors_of, output_of};
+
+    /// Compile a program and run it on a fresh VM, returning what it printed or the error
+    /// messages it reported
+    fn run_on_vm(source: &str) -> Result<String, Vec<String>> {
+        let statements = parse_source(source).expect("Program failed to parse");
+        let chunk = Compiler::new()
+            .compile(&statements)
+            .expect("Program failed to compile");
+
+        let output = SharedOutput::default();
+        let mut vm = Vm::with_output(Box::new(output.clone()));
+        match vm.run(&chunk) {
+            Ok(()) => Ok(output.contents()),
+            Err(error) => Err(vec![Diagnostic::from(error).message]),
+        }
+    }
+
+    #[test]
+    fn prints_the_same_as_the_tree_walker() {
+        let programs = [
+            "print 1 + 2 * 3 - 4 / 2;",
+            "print (1 + 2) * 3; print -(4 % 3); print 2 ** 3 ** 2;",
+            "print 1 < 2; print 2 <= 2; print 3 > 4; print 4 >= 5; print 1 == 1; print 1 != 1;",
+            r#"print "a" + "b"; print "ab" * 3; print 2 * "c";"#,
+            r#"print !nil; print !0; print !""; print nil == false; print "1" == 1;"#,
+            "print nil or 1; print nil and 1; print false or nil; print 1 and 2;",
+            "print true ? 1 : 2; print (1, 2, 3); print [1, [2, 3]];",
+            "var a = 1; var b; print a; print b; a = b = 2; print a; print b;",
+            "var a = 1; { var b = a + 1; { var a = b * 2; print a; print b; } print b; } print a;",
+            "var x = 3; if (x > 2) print \"big\"; else print \"small\";",
+            "var x = 0; if (x) { print \"zero is truthy\"; } print x;",
+        ];
+
+        for program in programs {
+            assert_eq!(run_on_vm(program), Ok(output_of(program)), "{program}");
+        }
+    }
+
+    #[test]
+    fn reports_the_same_errors_as_the_tree_walker() {
+        let programs = [
+            "print 1 + nil;",
+            r#"print -"a";"#,
+            "print 1 / 0;",
+            "print 5 % 0;",
+            r#"print "a" * -1;"#,
+            "print undefined;",
+            "missing = 1;",
+        ];
+
+        for program in programs {
+            assert_eq!(run_on_vm(program), Err(errors_of(program)), "{program}");
+        }
+    }
+}
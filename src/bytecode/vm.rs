@@ -0,0 +1,184 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::error::{RuntimeError, RuntimeErrorKind, RuntimeResult};
+use crate::interpreter::Interpreter;
+use crate::symbol::Symbol;
+use crate::value::Value;
+
+use super::chunk::{Chunk, Instruction, Origin};
+
+/// Runs compiled chunks on a stack of values. Global variables are kept between runs, so that a
+/// program can be compiled and run a piece at a time, as in a REPL.
+pub struct Vm {
+    globals: HashMap<Symbol, Value>,
+    /// Operands and intermediate results, with local variables in the slots at the bottom
+    stack: Vec<Value>,
+    /// Where `print` instructions are written
+    output: Box<dyn Write>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    /// Create a VM that prints to standard output
+    pub fn new() -> Self {
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    /// Create a VM that writes printed values to `output`, such as a buffer to be inspected
+    /// afterwards
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        Vm {
+            globals: HashMap::new(),
+            stack: Vec::new(),
+            output,
+        }
+    }
+
+    pub fn run(&mut self, chunk: &Chunk) -> RuntimeResult<()> {
+        let result = self.execute(chunk);
+
+        // A failed run leaves its operands behind, which the next run mustn't mistake for locals
+        self.stack.clear();
+        result
+    }
+
+    fn execute(&mut self, chunk: &Chunk) -> RuntimeResult<()> {
+        let mut ip = 0;
+
+        while let Some(&instruction) = chunk.code.get(ip) {
+            let origin = &chunk.origins[ip];
+            ip += 1;
+
+            match instruction {
+                Instruction::Constant(index) => self.push(chunk.constants[index].clone()),
+                Instruction::Nil => self.push(Value::Nil),
+                Instruction::True => self.push(Value::Boolean(true)),
+                Instruction::False => self.push(Value::Boolean(false)),
+                Instruction::Pop => {
+                    self.pop();
+                }
+                Instruction::BuildList(length) => {
+                    let elements = self.stack.split_off(self.stack.len() - length);
+                    self.push(Value::List(Rc::new(RefCell::new(elements))));
+                }
+
+                Instruction::DefineGlobal(name) => {
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                Instruction::GetGlobal(name) => match self.globals.get(&name) {
+                    Some(value) => self.push(value.clone()),
+                    None => return Err(Self::undefined_variable(origin)),
+                },
+                Instruction::SetGlobal(name) => {
+                    let value = self.peek().clone();
+                    match self.globals.get_mut(&name) {
+                        Some(existing) => *existing = value,
+                        None => return Err(Self::undefined_variable(origin)),
+                    }
+                }
+                Instruction::GetLocal(slot) => self.push(self.stack[slot].clone()),
+                Instruction::SetLocal(slot) => self.stack[slot] = self.peek().clone(),
+
+                Instruction::Add
+                | Instruction::Subtract
+                | Instruction::Multiply
+                | Instruction::Divide
+                | Instruction::Modulo
+                | Instruction::Power
+                | Instruction::Equal
+                | Instruction::NotEqual
+                | Instruction::Greater
+                | Instruction::GreaterEqual
+                | Instruction::Less
+                | Instruction::LessEqual => {
+                    let origin = Self::origin(origin);
+                    let right = self.pop();
+                    let left = self.pop();
+                    // The VM has no options yet, so operators behave as they do by default
+                    let result =
+                        Interpreter::binary_operation(&origin.token, left, right, false, None);
+                    self.push(Self::locate(result, origin)?);
+                }
+                Instruction::Negate | Instruction::Not => {
+                    let origin = Self::origin(origin);
+                    let right = self.pop();
+                    let result = Interpreter::unary_operation(&origin.token, right);
+                    self.push(Self::locate(result, origin)?);
+                }
+
+                Instruction::Jump(target) => ip = target,
+                Instruction::JumpIfFalse(target) => {
+                    if !self.peek().is_truthy() {
+                        ip = target;
+                    }
+                }
+                Instruction::JumpIfTrue(target) => {
+                    if self.peek().is_truthy() {
+                        ip = target;
+                    }
+                }
+
+                Instruction::Print(count) => {
+                    let values = self.stack.split_off(self.stack.len() - count);
+                    let line = values
+                        .iter()
+                        .map(Value::to_string)
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    writeln!(self.output, "{}", line).expect("Failed to write program output");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack
+            .pop()
+            .expect("Compiled code popped from an empty stack")
+    }
+
+    fn peek(&self) -> &Value {
+        self.stack
+            .last()
+            .expect("Compiled code peeked at an empty stack")
+    }
+
+    /// Origin of an instruction that can fail, which the compiler always records
+    fn origin(origin: &Option<Origin>) -> &Origin {
+        origin
+            .as_ref()
+            .expect("Instruction that can fail was compiled without an origin")
+    }
+
+    /// Mark an operator's error with the span of its expression
+    fn locate(result: RuntimeResult<Value>, origin: &Origin) -> RuntimeResult<Value> {
+        match origin.span {
+            Some(span) => result.map_err(|error| error.with_span(span)),
+            None => result,
+        }
+    }
+
+    fn undefined_variable(origin: &Option<Origin>) -> RuntimeError {
+        let name = &Self::origin(origin).token;
+        RuntimeError::new(
+            RuntimeErrorKind::UndefinedVariable,
+            format!("Undefined variable '{}'.", name.lexeme),
+            name,
+        )
+    }
+}
@@ -0,0 +1,233 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::error::{RuntimeError, RuntimeErrorKind, RuntimeResult, Unwind};
+use crate::interpreter::Interpreter;
+use crate::statement::FunctionDeclaration;
+use crate::symbol::Symbol;
+use crate::token::Token;
+use crate::value::Value;
+
+/// Anything that can be called from Lox code with a parenthesised argument list
+pub trait LoxCallable {
+    /// The number of arguments the callable expects
+    fn arity(&self) -> usize;
+
+    /// The name the callable was declared with, or `None` for an anonymous function
+    fn name(&self) -> Option<&str>;
+
+    /// Invoke the callable. The argument count has already been checked against `arity`, and
+    /// `paren` is the closing parenthesis of the call, used to locate runtime errors.
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> RuntimeResult<Value>;
+}
+
+/// A function implemented in Rust and exposed to Lox code as a global
+#[derive(Clone, Copy)]
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: fn(&[Value]) -> Result<Value, String>,
+}
+
+impl LoxCallable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(self.name)
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> RuntimeResult<Value> {
+        (self.function)(&arguments)
+            .map_err(|message| RuntimeError::new(RuntimeErrorKind::NativeFunction, message, paren))
+    }
+}
+
+/// A function or method declared in Lox code
+pub struct LoxFunction {
+    declaration: Rc<FunctionDeclaration>,
+    /// The scope the function was declared in
+    closure: Rc<RefCell<Environment>>,
+    /// Whether this is a class's `init` method, which always returns the instance
+    is_initializer: bool,
+}
+
+impl LoxFunction {
+    pub fn new(
+        declaration: Rc<FunctionDeclaration>,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool,
+    ) -> Self {
+        LoxFunction {
+            declaration,
+            closure,
+            is_initializer,
+        }
+    }
+
+    /// Create a copy of this method whose body sees `this` as the given instance
+    pub fn bind(&self, instance: Value) -> LoxFunction {
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        environment.define(Symbol::intern("this"), instance);
+
+        LoxFunction::new(
+            Rc::clone(&self.declaration),
+            Rc::new(RefCell::new(environment)),
+            self.is_initializer,
+        )
+    }
+
+    /// The instance an initializer was bound to
+    fn bound_instance(&self) -> Value {
+        self.closure
+            .borrow()
+            .get_own(Symbol::intern("this"))
+            .expect("Initializer should be bound to an instance")
+    }
+}
+
+impl LoxCallable for LoxFunction {
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.declaration
+            .name
+            .as_ref()
+            .map(|name| name.lexeme.as_str())
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+        _paren: &Token,
+    ) -> RuntimeResult<Value> {
+        // Each call gets its own scope for the parameters, nested inside the closure
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        for (param, argument) in self.declaration.params.iter().zip(arguments) {
+            environment.define(param.symbol(), argument);
+        }
+
+        // A return statement unwinds to here, while falling off the end of the body returns nil
+        match interpreter.execute_block(&self.declaration.body, environment) {
+            Ok(()) | Err(Unwind::Return(_)) if self.is_initializer => Ok(self.bound_instance()),
+            Ok(()) => Ok(Value::Nil),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(error)) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{errors_of, output_of, values_of};
+    use crate::Value;
+
+    #[test]
+    fn calls_user_defined_functions() {
+        let source = "fun add(a, b) { print a + b; } add(1, 2); add(\"x\", \"y\");";
+        assert_eq!(output_of(source), "3\nxy\n");
+    }
+
+    #[test]
+    fn returns_early_from_inside_a_loop() {
+        let source = "
+            fun sign(n) {
+                while (n < 0) return -1;
+                while (n == 0) { return; }
+                return 1;
+            }
+            sign(-5); sign(0); sign(3);
+        ";
+        let values = values_of(source);
+        assert!(values == [Value::Number(-1.0), Value::Nil, Value::Number(1.0)]);
+    }
+
+    #[test]
+    fn closures_keep_captured_variables_alive() {
+        let source = "
+            fun makeCounter() {
+                var count = 0;
+                fun counter() {
+                    count = count + 1;
+                    return count;
+                }
+                return counter;
+            }
+            var first = makeCounter();
+            var second = makeCounter();
+            first(); first(); second(); first();
+        ";
+        let values = values_of(source);
+        assert!(
+            values
+                == [
+                    Value::Number(1.0),
+                    Value::Number(2.0),
+                    Value::Number(1.0),
+                    Value::Number(3.0)
+                ]
+        );
+    }
+
+    #[test]
+    fn calls_anonymous_functions_passed_as_arguments() {
+        let source = "
+            fun twice(f, x) { return f(f(x)); }
+            var offset = 3;
+            print twice(fun (n) { return n + offset; }, 1);
+            print fun (a, b) { return a * b; };
+        ";
+        assert_eq!(output_of(source), "7\n<fn>\n");
+    }
+
+    #[test]
+    fn reports_arity_and_name() {
+        let values = values_of("fun add(a, b) { return a + b; } add; clock; fun (x) {};");
+        let callables = values
+            .into_iter()
+            .map(|value| match value {
+                Value::Callable(callable) => (callable.arity(), callable.name().map(String::from)),
+                _ => panic!("Expected a callable"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            callables,
+            [
+                (2, Some("add".to_string())),
+                (0, Some("clock".to_string())),
+                (1, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_calls_with_the_wrong_number_of_arguments() {
+        assert_eq!(
+            errors_of("fun add(a, b) { return a + b; } add(1);"),
+            ["Expected 2 arguments but got 1."]
+        );
+        assert_eq!(
+            errors_of(r#"len("a", "b");"#),
+            ["Expected 1 arguments but got 2."]
+        );
+        assert_eq!(
+            errors_of("class A { init(x) {} } A();"),
+            ["Expected 1 arguments but got 0."]
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::callable::{LoxCallable, LoxFunction};
+use crate::error::{RuntimeError, RuntimeErrorKind, RuntimeResult};
+use crate::interpreter::Interpreter;
+use crate::symbol::Symbol;
+use crate::token::Token;
+use crate::value::Value;
+
+/// A class declared in Lox code. Cloning is cheap, as the method table is shared.
+#[derive(Clone)]
+pub struct LoxClass {
+    pub name: String,
+    superclass: Option<Rc<LoxClass>>,
+    methods: Rc<HashMap<Symbol, Rc<LoxFunction>>>,
+}
+
+impl LoxClass {
+    pub fn new(
+        name: String,
+        superclass: Option<Rc<LoxClass>>,
+        methods: HashMap<Symbol, Rc<LoxFunction>>,
+    ) -> Self {
+        LoxClass {
+            name,
+            superclass,
+            methods: Rc::new(methods),
+        }
+    }
+
+    /// Look up a method on this class, falling back to the superclass chain if it is not
+    /// defined here
+    pub fn find_method(&self, name: Symbol) -> Option<Rc<LoxFunction>> {
+        self.methods.get(&name).cloned().or_else(|| {
+            self.superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name))
+        })
+    }
+}
+
+/// Calling a class creates a new instance, running its `init` method if it has one
+impl LoxCallable for LoxClass {
+    fn arity(&self) -> usize {
+        self.find_method(Symbol::intern("init"))
+            .map_or(0, |initializer| initializer.arity())
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> RuntimeResult<Value> {
+        let instance = Value::Instance(Rc::new(RefCell::new(LoxInstance::new(self.clone()))));
+
+        if let Some(initializer) = self.find_method(Symbol::intern("init")) {
+            initializer
+                .bind(instance.clone())
+                .call(interpreter, arguments, paren)?;
+        }
+
+        Ok(instance)
+    }
+}
+
+pub struct LoxInstance {
+    pub class: LoxClass,
+    fields: HashMap<Symbol, Value>,
+}
+
+impl LoxInstance {
+    pub fn new(class: LoxClass) -> Self {
+        LoxInstance {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    /// Look up a property, preferring fields over methods. Methods are bound to the instance so
+    /// they can refer to it as `this`.
+    pub fn get(instance: &Rc<RefCell<LoxInstance>>, name: &Token) -> RuntimeResult<Value> {
+        if let Some(value) = instance.borrow().fields.get(&name.symbol()) {
+            return Ok(value.clone());
+        }
+
+        let method = instance.borrow().class.find_method(name.symbol());
+        match method {
+            Some(method) => {
+                let bound = method.bind(Value::Instance(Rc::clone(instance)));
+                Ok(Value::Callable(Rc::new(bound)))
+            }
+            None => Err(RuntimeError::new(
+                RuntimeErrorKind::UndefinedProperty,
+                format!("Undefined property '{}'.", name.lexeme),
+                name,
+            )),
+        }
+    }
+
+    pub fn set(&mut self, name: &Token, value: Value) {
+        self.fields.insert(name.symbol(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{errors_of, values_of};
+    use crate::Value;
+
+    #[test]
+    fn methods_see_fields_set_by_init_through_this() {
+        let source = r#"
+            class Person {
+                init(name) { this.name = name; }
+                getName() { return this.name; }
+            }
+            Person("Ada").getName();
+        "#;
+        let values = values_of(source);
+        assert!(values.last() == Some(&Value::String("Ada".into())));
+    }
+
+    #[test]
+    fn subclasses_call_superclass_methods_through_super() {
+        let source = r#"
+            class Doughnut {
+                cook() { return "Fry until golden brown."; }
+            }
+            class BostonCream < Doughnut {
+                cook() {
+                    return super.cook() + " Pipe full of custard and coat with chocolate.";
+                }
+            }
+            BostonCream().cook();
+        "#;
+        assert!(
+            values_of(source)
+                == [Value::String(
+                    "Fry until golden brown. Pipe full of custard and coat with chocolate.".into()
+                )]
+        );
+    }
+
+    #[test]
+    fn subclasses_inherit_methods() {
+        let source = "class A { name() { return \"A\"; } } class B < A {} B().name();";
+        assert!(values_of(source) == [Value::String("A".into())]);
+    }
+
+    #[test]
+    fn rejects_a_superclass_that_is_not_a_class() {
+        assert_eq!(
+            errors_of("var NotAClass = 1; class A < NotAClass {}"),
+            ["Superclass must be a class."]
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use std::rc::Rc;
+
+use crate::expression::Expression;
+use crate::interpreter::Interpreter;
+use crate::statement::{FunctionDeclaration, Statement};
+use crate::token::Literal;
+use crate::value::Value;
+
+/// Optional pass run between parsing and resolving that replaces operators applied to literals,
+/// such as `2 + 3 * 4`, with the literal they evaluate to, so that they are not worked out again
+/// each time they run. Operators are applied exactly as the interpreter would, using its options,
+/// and any that fail, such as `1 / 0`, are left in place to report their error at runtime.
+pub struct ConstantFolder<'a> {
+    interpreter: &'a Interpreter,
+}
+
+impl<'a> ConstantFolder<'a> {
+    pub fn new(interpreter: &'a Interpreter) -> Self {
+        ConstantFolder { interpreter }
+    }
+
+    pub fn fold(&self, statements: Vec<Statement>) -> Vec<Statement> {
+        statements
+            .into_iter()
+            .map(|statement| self.fold_statement(statement))
+            .collect()
+    }
+
+    fn fold_statement(&self, statement: Statement) -> Statement {
+        match statement {
+            Statement::Block(statements) => Statement::Block(self.fold(statements)),
+            Statement::Class {
+                name,
+                superclass,
+                methods,
+            } => Statement::Class {
+                name,
+                superclass,
+                methods: methods
+                    .into_iter()
+                    .map(|method| self.fold_function(method))
+                    .collect(),
+            },
+            Statement::Expression(expression) => {
+                Statement::Expression(self.fold_expression(expression))
+            }
+            Statement::Function(declaration) => {
+                Statement::Function(self.fold_function(declaration))
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => Statement::If {
+                condition: self.fold_expression(condition),
+                then_branch: Box::new(self.fold_statement(*then_branch)),
+                else_branch: else_branch.map(|branch| Box::new(self.fold_statement(*branch))),
+            },
+            Statement::Print {
+                keyword,
+                expressions,
+            } => Statement::Print {
+                keyword,
+                expressions: self.fold_all(expressions),
+            },
+            Statement::Return { keyword, value } => Statement::Return {
+                keyword,
+                value: value.map(|value| self.fold_expression(value)),
+            },
+            Statement::Var { name, initializer } => Statement::Var {
+                name,
+                initializer: initializer.map(|initializer| self.fold_expression(initializer)),
+            },
+            Statement::While {
+                keyword,
+                condition,
+                body,
+            } => Statement::While {
+                keyword,
+                condition: self.fold_expression(condition),
+                body: Box::new(self.fold_statement(*body)),
+            },
+        }
+    }
+
+    fn fold_function(&self, declaration: Rc<FunctionDeclaration>) -> Rc<FunctionDeclaration> {
+        let declaration = Rc::unwrap_or_clone(declaration);
+        Rc::new(FunctionDeclaration {
+            body: self.fold(declaration.body),
+            ..declaration
+        })
+    }
+
+    fn fold_all(&self, expressions: Vec<Expression>) -> Vec<Expression> {
+        expressions
+            .into_iter()
+            .map(|expression| self.fold_expression(expression))
+            .collect()
+    }
+
+    fn fold_expression(&self, expression: Expression) -> Expression {
+        let span = expression.span();
+
+        match expression {
+            Expression::Assign { name, value } => Expression::Assign {
+                name,
+                value: Box::new(self.fold_expression(*value)),
+            },
+            Expression::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.fold_expression(*left);
+                let right = self.fold_expression(*right);
+
+                if let (Some(left_value), Some(right_value)) = (value(&left), value(&right)) {
+                    let result = self
+                        .interpreter
+                        .apply_binary(&operator, left_value, right_value);
+                    if let Some(literal) = result.ok().and_then(literal) {
+                        return Expression::Literal {
+                            value: literal,
+                            span,
+                        };
+                    }
+                }
+
+                Expression::Binary {
+                    left: Box::new(left),
+                    operator,
+                    right: Box::new(right),
+                }
+            }
+            Expression::Call {
+                callee,
+                paren,
+                arguments,
+            } => Expression::Call {
+                callee: Box::new(self.fold_expression(*callee)),
+                paren,
+                arguments: self.fold_all(arguments),
+            },
+            Expression::Comma { expressions } => Expression::Comma {
+                expressions: self.fold_all(expressions),
+            },
+            Expression::Get { object, name } => Expression::Get {
+                object: Box::new(self.fold_expression(*object)),
+                name,
+            },
+            Expression::Grouping { expression, span } => match self.fold_expression(*expression) {
+                // The literal takes over the parentheses, so that errors about it cover them
+                Expression::Literal { value, .. } => Expression::Literal { value, span },
+                expression => Expression::Grouping {
+                    expression: Box::new(expression),
+                    span,
+                },
+            },
+            Expression::Index {
+                collection,
+                bracket,
+                index,
+                span,
+            } => Expression::Index {
+                collection: Box::new(self.fold_expression(*collection)),
+                bracket,
+                index: Box::new(self.fold_expression(*index)),
+                span,
+            },
+            Expression::IndexSet {
+                collection,
+                bracket,
+                index,
+                value,
+            } => Expression::IndexSet {
+                collection: Box::new(self.fold_expression(*collection)),
+                bracket,
+                index: Box::new(self.fold_expression(*index)),
+                value: Box::new(self.fold_expression(*value)),
+            },
+            Expression::Lambda {
+                keyword,
+                declaration,
+                span,
+            } => Expression::Lambda {
+                keyword,
+                declaration: self.fold_function(declaration),
+                span,
+            },
+            Expression::ListLiteral { elements, span } => Expression::ListLiteral {
+                elements: self.fold_all(elements),
+                span,
+            },
+            Expression::Logical {
+                left,
+                operator,
+                right,
+            } => Expression::Logical {
+                left: Box::new(self.fold_expression(*left)),
+                operator,
+                right: Box::new(self.fold_expression(*right)),
+            },
+            Expression::Set {
+                object,
+                name,
+                value,
+            } => Expression::Set {
+                object: Box::new(self.fold_expression(*object)),
+                name,
+                value: Box::new(self.fold_expression(*value)),
+            },
+            Expression::Ternary {
+                condition,
+                then_expr,
+                else_expr,
+            } => Expression::Ternary {
+                condition: Box::new(self.fold_expression(*condition)),
+                then_expr: Box::new(self.fold_expression(*then_expr)),
+                else_expr: Box::new(self.fold_expression(*else_expr)),
+            },
+            Expression::Unary { operator, right } => {
+                let right = self.fold_expression(*right);
+
+                if let Some(right_value) = value(&right) {
+                    let result = Interpreter::unary_operation(&operator, right_value);
+                    if let Some(literal) = result.ok().and_then(literal) {
+                        return Expression::Literal {
+                            value: literal,
+                            span,
+                        };
+                    }
+                }
+
+                Expression::Unary {
+                    operator,
+                    right: Box::new(right),
+                }
+            }
+            expression @ (Expression::Literal { .. }
+            | Expression::Super { .. }
+            | Expression::This { .. }
+            | Expression::Variable { .. }) => expression,
+        }
+    }
+}
+
+/// The value of an expression, if it is a literal
+fn value(expression: &Expression) -> Option<Value> {
+    match expression {
+        Expression::Literal { value, .. } => Some(Value::from(value.clone())),
+        _ => None,
+    }
+}
+
+/// The literal for a value, if it can be written as one
+fn literal(value: Value) -> Option<Literal> {
+    match value {
+        Value::String(str) => Some(Literal::String(str.to_string())),
+        // Infinity and NaN have no literal syntax, so they are left to be worked out at runtime
+        Value::Number(num) if num.is_finite() => Some(Literal::Number(num)),
+        Value::Boolean(bool) => Some(Literal::Boolean(bool)),
+        Value::Nil => Some(Literal::None),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConstantFolder;
+    use crate::{parse_source, Interpreter, ToSource};
+
+    /// Fold a program and format it back into source
+    fn fold(source: &str) -> String {
+        let statements = parse_source(source).expect("Program failed to parse");
+        let interpreter = Interpreter::new();
+        ConstantFolder::new(&interpreter)
+            .fold(statements)
+            .to_source()
+    }
+
+    #[test]
+    fn leaves_repetition_too_long_to_build() {
+        assert_eq!(fold(r#"print "ab" * 4e18;"#), "print \"ab\" * 4e18;\n");
+    }
+
+    #[test]
+    fn folds_literal_arithmetic() {
+        assert_eq!(fold("print 2 + 3 * 4;"), "print 14;\n");
+        assert_eq!(
+            fold(r#"var s = "a" + "b"; print -(1 - 3), !true, 1 < 2;"#),
+            "var s = \"ab\";\nprint 2, false, true;\n"
+        );
+    }
+
+    #[test]
+    fn leaves_operations_that_fail_or_read_variables() {
+        assert_eq!(fold("print 1 / 0;"), "print 1 / 0;\n");
+        assert_eq!(fold("print 2 * 3 / 0;"), "print 6 / 0;\n");
+        assert_eq!(fold(r#"print 1 + "a";"#), "print 1 + \"a\";\n");
+        assert_eq!(fold("var x; print x + 1 * 2;"), "var x;\nprint x + 2;\n");
+    }
+}
@@ -0,0 +1,170 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::error::{RuntimeError, RuntimeErrorKind, RuntimeResult};
+use crate::symbol::Symbol;
+use crate::token::Token;
+use crate::value::Value;
+
+/// A single scope of variable bindings. Scopes are shared through `Rc<RefCell<_>>` so that
+/// functions can hold on to the scope they were defined in.
+pub struct Environment {
+    /// Names bound in this scope, where `None` is a variable declared but not yet assigned
+    values: HashMap<Symbol, Option<Value>>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing: None,
+        }
+    }
+
+    /// Create a new scope nested inside the given one
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// Bind a name to a value, replacing any existing binding of the same name
+    pub fn define(&mut self, name: Symbol, value: Value) {
+        self.values.insert(name, Some(value));
+    }
+
+    /// Bind a name without giving it a value, so that reading it is an error until it has been
+    /// assigned
+    pub fn declare(&mut self, name: Symbol) {
+        self.values.insert(name, None);
+    }
+
+    /// Look up a name in this scope only, without searching enclosing scopes
+    pub fn get_own(&self, name: Symbol) -> Option<Value> {
+        self.values.get(&name).cloned().flatten()
+    }
+
+    /// Every name bound in this scope only, sorted by name, with `None` for variables declared
+    /// but not yet assigned
+    pub fn bindings(&self) -> Vec<(String, Option<Value>)> {
+        let mut bindings = self
+            .values
+            .iter()
+            .map(|(name, value)| (name.as_string(), value.clone()))
+            .collect::<Vec<_>>();
+        bindings.sort_by(|(left, _), (right, _)| left.cmp(right));
+        bindings
+    }
+
+    pub fn get(&self, name: &Token) -> RuntimeResult<Value> {
+        match (self.values.get(&name.symbol()), &self.enclosing) {
+            (Some(value), _) => Self::assigned(name, value),
+            (None, Some(enclosing)) => enclosing.borrow().get(name),
+            (None, None) => Err(Self::undefined_variable(name)),
+        }
+    }
+
+    /// Look up a name in the scope `distance` hops out from this one, as worked out by the
+    /// resolver
+    pub fn get_at(&self, distance: usize, name: &Token) -> RuntimeResult<Value> {
+        match (distance, &self.enclosing) {
+            (0, _) => match self.values.get(&name.symbol()) {
+                Some(value) => Self::assigned(name, value),
+                None => Err(Self::undefined_variable(name)),
+            },
+            (_, Some(enclosing)) => enclosing.borrow().get_at(distance - 1, name),
+            (_, None) => Err(Self::undefined_variable(name)),
+        }
+    }
+
+    /// Rebind a name in the scope `distance` hops out from this one, as worked out by the
+    /// resolver
+    pub fn assign_at(&mut self, distance: usize, name: &Token, value: Value) -> RuntimeResult<()> {
+        match (distance, &self.enclosing) {
+            (0, _) => self.assign(name, value),
+            (_, Some(enclosing)) => enclosing.borrow_mut().assign_at(distance - 1, name, value),
+            (_, None) => Err(Self::undefined_variable(name)),
+        }
+    }
+
+    /// Rebind an existing name to a new value. Unlike `define`, this fails if the name has not
+    /// been declared.
+    pub fn assign(&mut self, name: &Token, value: Value) -> RuntimeResult<()> {
+        match (self.values.get_mut(&name.symbol()), &self.enclosing) {
+            (Some(existing), _) => {
+                *existing = Some(value);
+                Ok(())
+            }
+            (None, Some(enclosing)) => enclosing.borrow_mut().assign(name, value),
+            (None, None) => Err(Self::undefined_variable(name)),
+        }
+    }
+
+    /// The value bound to a name, unless it has only been declared
+    fn assigned(name: &Token, value: &Option<Value>) -> RuntimeResult<Value> {
+        value.clone().ok_or_else(|| {
+            RuntimeError::new(
+                RuntimeErrorKind::UnassignedVariable,
+                format!("Variable '{}' used before assignment.", name.lexeme),
+                name,
+            )
+        })
+    }
+
+    fn undefined_variable(name: &Token) -> RuntimeError {
+        RuntimeError::new(
+            RuntimeErrorKind::UndefinedVariable,
+            format!("Undefined variable '{}'.", name.lexeme),
+            name,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::Environment;
+    use crate::symbol::Symbol;
+    use crate::token::{Literal, Token, TokenType};
+    use crate::value::Value;
+
+    fn name(lexeme: &str) -> Token {
+        Token::new(
+            TokenType::Identifier,
+            lexeme.to_string(),
+            Literal::None,
+            1,
+            1,
+            0,
+        )
+    }
+
+    #[test]
+    fn inner_scopes_shadow_outer_bindings() {
+        let outer = Rc::new(RefCell::new(Environment::new()));
+        outer
+            .borrow_mut()
+            .define(Symbol::intern("a"), Value::Number(1.0));
+        outer
+            .borrow_mut()
+            .define(Symbol::intern("b"), Value::Number(2.0));
+
+        let mut inner = Environment::with_enclosing(Rc::clone(&outer));
+        inner.define(Symbol::intern("a"), Value::Number(3.0));
+
+        assert!(inner.get(&name("a")).ok() == Some(Value::Number(3.0)));
+        assert!(inner.get(&name("b")).ok() == Some(Value::Number(2.0)));
+        assert!(outer.borrow().get(&name("a")).ok() == Some(Value::Number(1.0)));
+    }
+}
@@ -1,58 +1,263 @@
-use crate::token::{Token, TokenType};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::fmt;
 
-static ERROR_FLAG: AtomicBool = AtomicBool::new(false);
-static RUNTIME_ERROR_FLAG: AtomicBool = AtomicBool::new(false);
+use crate::token::{Span, Token, TokenType};
+use crate::util::json_string;
+use crate::value::Value;
 
-pub fn lox_generic_error(line: usize, message: &str) {
-    report_error(line, None, message);
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiagnosticKind {
+    Scan,
+    Parse,
+    Resolve,
+    /// A construct the bytecode compiler can't translate
+    #[cfg(feature = "bytecode")]
+    Compile,
+    Runtime,
 }
 
-pub fn report_error(line: usize, r#where: Option<&str>, message: &str) {
-    if r#where.is_none() {
-        eprintln!("[line: {}] Error: {}", line, message);
-    } else {
-        eprintln!(
-            "[line: {}] Error {}: {}",
+/// Whether a diagnostic stops the program from running
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    /// Likely to be a mistake, but the program can still run
+    Warning,
+}
+
+/// An error found while scanning, parsing or interpreting, collected and handed back to the
+/// caller rather than printed on the spot
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub severity: Severity,
+    pub line: usize,
+    pub column: Option<usize>,
+    /// Description of where on the line the error occurred, such as `at 'x'`
+    pub location: Option<String>,
+    pub message: String,
+    /// Characters of source at fault, when the error is about more than the token at `column`
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    pub fn scan(line: usize, column: usize, message: &str) -> Self {
+        Diagnostic {
+            kind: DiagnosticKind::Scan,
+            severity: Severity::Error,
             line,
-            r#where.expect("Error location not provided"),
-            message
-        );
+            column: Some(column),
+            location: None,
+            message: message.to_string(),
+            span: None,
+        }
+    }
+
+    pub fn parse(token: &Token, message: String) -> Self {
+        Self::at_token(DiagnosticKind::Parse, token, message)
+    }
+
+    pub fn resolve(token: &Token, message: String) -> Self {
+        Self::at_token(DiagnosticKind::Resolve, token, message)
+    }
+
+    /// A warning found by the resolver, which doesn't stop the program from running
+    pub fn resolve_warning(span: Span, message: String) -> Self {
+        Diagnostic {
+            kind: DiagnosticKind::Resolve,
+            severity: Severity::Warning,
+            line: span.line,
+            column: Some(span.column),
+            location: None,
+            message,
+            span: Some(span),
+        }
+    }
+
+    #[cfg(feature = "bytecode")]
+    pub fn compile(token: &Token, message: String) -> Self {
+        Self::at_token(DiagnosticKind::Compile, token, message)
+    }
+
+    fn at_token(kind: DiagnosticKind, token: &Token, message: String) -> Self {
+        let location = if token.token_type == TokenType::Eof {
+            "at end of input".to_string()
+        } else {
+            format!("at '{}'", token.lexeme)
+        };
+
+        Diagnostic {
+            kind,
+            severity: Severity::Error,
+            line: token.line,
+            column: Some(token.column),
+            location: Some(location),
+            message,
+            span: None,
+        }
     }
-    set_error_flag(true);
 }
 
-pub fn parse_error(token: Token, message: String) {
-    if token.token_type == TokenType::Eof {
-        report_error(token.line, Some("at end of input"), &message)
-    } else {
-        report_error(
-            token.line,
-            Some(&format!("at '{}'", token.lexeme)),
-            &message,
-        )
+impl From<RuntimeError> for Diagnostic {
+    fn from(error: RuntimeError) -> Self {
+        Diagnostic {
+            kind: DiagnosticKind::Runtime,
+            severity: Severity::Error,
+            line: error.line,
+            column: Some(error.column),
+            location: None,
+            message: error.message,
+            span: error.span,
+        }
     }
 }
 
-pub fn runtime_error(error: RuntimeError) {
-    lox_generic_error(error.token.line, &error.message);
-    set_runtime_error_flag(true);
+impl Diagnostic {
+    /// Where the error occurred, such as `[line: 1, column: 5] Error at 'x'`, without the message
+    fn header(&self) -> String {
+        let severity = match self.severity {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        };
+        let mut header = match self.column {
+            Some(column) => format!("[line: {}, column: {}] {severity}", self.line, column),
+            None => format!("[line: {}] {severity}", self.line),
+        };
+        if let Some(location) = &self.location {
+            header.push(' ');
+            header.push_str(location);
+        }
+        header
+    }
 }
 
-pub fn set_error_flag(value: bool) {
-    ERROR_FLAG.store(value, Ordering::SeqCst);
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.header(), self.message)
+    }
 }
 
-pub fn get_error_flag() -> bool {
-    ERROR_FLAG.load(Ordering::SeqCst)
+/// How an [`ErrorReporter`] presents diagnostics
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ReportFormat {
+    /// Human readable text
+    #[default]
+    Plain,
+    /// Human readable text, with the header and caret highlighted using ANSI escape codes, in red
+    /// for errors and yellow for warnings
+    Coloured,
+    /// One JSON object per diagnostic, with `line`, `column`, `severity` and `message` fields,
+    /// for consumption by editors and other tools
+    Json,
 }
 
-fn set_runtime_error_flag(value: bool) {
-    RUNTIME_ERROR_FLAG.store(value, Ordering::SeqCst);
+/// Formats diagnostics together with the line of source they refer to, marking the column of
+/// the error with a caret:
+///
+/// ```text
+/// [line: 1, column: 9] Error at ';': Expected expression.
+///     1 | var x = ;
+///       |         ^
+/// ```
+///
+/// or in whichever other [`ReportFormat`] it was created with.
+pub struct ErrorReporter {
+    lines: Vec<String>,
+    /// Number of characters before each line of the source, to place spans on lines
+    line_starts: Vec<usize>,
+    format: ReportFormat,
 }
 
-pub fn get_runtime_error_flag() -> bool {
-    RUNTIME_ERROR_FLAG.load(Ordering::SeqCst)
+impl ErrorReporter {
+    pub fn new(source: &str, format: ReportFormat) -> Self {
+        let mut line_starts = vec![0];
+        for (offset, c) in source.chars().enumerate() {
+            if c == '\n' {
+                line_starts.push(offset + 1);
+            }
+        }
+
+        ErrorReporter {
+            lines: source.lines().map(String::from).collect(),
+            line_starts,
+            format,
+        }
+    }
+
+    pub fn format(&self, diagnostic: &Diagnostic) -> String {
+        if self.format == ReportFormat::Json {
+            return Self::format_json(diagnostic);
+        }
+
+        let mut builder = format!(
+            "{}: {}",
+            self.paint(&diagnostic.header(), diagnostic.severity),
+            diagnostic.message
+        );
+
+        // Errors at the end of the input may point past the last line
+        let Some((line, line_start)) = diagnostic.line.checked_sub(1).and_then(|i| {
+            let line = self.lines.get(i)?;
+            Some((line, self.line_starts[i]))
+        }) else {
+            return builder;
+        };
+
+        let gutter = diagnostic.line.to_string();
+        let padding = " ".repeat(gutter.len());
+        builder.push_str(&format!("\n    {gutter} | {line}"));
+
+        // Underline whatever part of the span is on this line, or else mark the column alone
+        let underline = diagnostic.span.and_then(|span| {
+            let line_end = line_start + line.chars().count();
+            let start = span.start.clamp(line_start, line_end);
+            let end = span.end.clamp(line_start, line_end);
+            (start < end).then_some((start - line_start + 1, end - start))
+        });
+
+        if let Some((column, width)) = underline.or(diagnostic.column.map(|column| (column, 1))) {
+            // Reuse any tabs from the source line so the caret lines up however they are shown
+            let indent = line
+                .chars()
+                .take(column.saturating_sub(1))
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+            let carets = "^".repeat(width);
+            builder.push_str(&format!(
+                "\n    {padding} | {indent}{}",
+                self.paint(&carets, diagnostic.severity)
+            ));
+        }
+
+        builder
+    }
+
+    fn format_json(diagnostic: &Diagnostic) -> String {
+        let column = diagnostic
+            .column
+            .map_or_else(|| "null".to_string(), |column| column.to_string());
+        let severity = match diagnostic.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        format!(
+            "{{\"line\":{},\"column\":{},\"severity\":\"{}\",\"message\":{}}}",
+            diagnostic.line,
+            column,
+            severity,
+            json_string(&diagnostic.message)
+        )
+    }
+
+    fn paint(&self, text: &str, severity: Severity) -> String {
+        if self.format != ReportFormat::Coloured {
+            return text.to_string();
+        }
+
+        let colour = match severity {
+            Severity::Error => 31,
+            Severity::Warning => 33,
+        };
+        format!("\x1b[{colour}m{text}\x1b[0m")
+    }
 }
 
 pub struct ParseError {
@@ -66,14 +271,124 @@ impl ParseError {
 }
 pub type ParseResult<T> = std::result::Result<T, ParseError>;
 
+/// Category of a runtime error, so that embedders can tell failures apart without inspecting
+/// the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    UndefinedVariable,
+    /// A variable declared without an initialiser was read before being assigned, which is only
+    /// an error when implicit `nil` is turned off
+    UnassignedVariable,
+    UndefinedProperty,
+    /// An operation was applied to a value of the wrong type
+    TypeMismatch,
+    DivisionByZero,
+    /// A function was called with the wrong number of arguments
+    Arity,
+    NotCallable,
+    IndexOutOfRange,
+    StackOverflow,
+    /// A loop ran more times than the interpreter allows
+    IterationLimit,
+    /// An operation would build a string longer than the interpreter allows
+    StringTooLong,
+    /// A native function rejected its arguments or otherwise failed
+    NativeFunction,
+}
+
 pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
     pub message: String,
-    token: Token,
+    /// Position of the token the error occurred at
+    line: usize,
+    column: usize,
+    span: Option<Span>,
 }
 
 impl RuntimeError {
-    pub fn new(message: String, token: Token) -> Self {
-        RuntimeError { message, token }
+    pub fn new(kind: RuntimeErrorKind, message: String, token: &Token) -> Self {
+        RuntimeError {
+            kind,
+            message,
+            line: token.line,
+            column: token.column,
+            span: None,
+        }
+    }
+
+    /// Mark the whole of the expression at fault, so that it is underlined when reported
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
     }
 }
 pub type RuntimeResult<T> = std::result::Result<T, RuntimeError>;
+
+/// Reasons for execution to stop partway through a statement and unwind up the call stack
+pub enum Unwind {
+    Error(RuntimeError),
+    /// A `return` statement, carrying the returned value up to the enclosing function call
+    Return(Value),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Self {
+        Unwind::Error(error)
+    }
+}
+pub type ExecuteResult<T> = std::result::Result<T, Unwind>;
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrorReporter, ReportFormat};
+    use crate::run_source;
+
+    /// Run a program which is expected to fail and format its errors as they would be reported
+    fn report(source: &str, format: ReportFormat) -> Vec<String> {
+        let reporter = ErrorReporter::new(source, format);
+        match run_source(source) {
+            Ok(_) => panic!("Program succeeded but was expected to fail"),
+            Err(diagnostics) => diagnostics
+                .iter()
+                .map(|diagnostic| reporter.format(diagnostic))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn places_a_caret_under_the_error() {
+        assert_eq!(
+            report("print 1;\nvar x = ;\n", ReportFormat::Plain),
+            [concat!(
+                "[line: 2, column: 9] Error at ';': Expected expression.\n",
+                "    2 | var x = ;\n",
+                "      |         ^"
+            )]
+        );
+    }
+
+    #[test]
+    fn only_colours_coloured_reports() {
+        let plain = report("print x;", ReportFormat::Plain);
+        assert_eq!(
+            plain,
+            [concat!(
+                "[line: 1, column: 7] Error: Undefined variable 'x'.\n",
+                "    1 | print x;\n",
+                "      |       ^"
+            )]
+        );
+
+        let coloured = report("print x;", ReportFormat::Coloured);
+        assert!(coloured[0].starts_with("\x1b[31m[line: 1, column: 7] Error\x1b[0m: "));
+        assert!(coloured[0].ends_with("\x1b[31m^\x1b[0m"));
+    }
+
+    #[test]
+    fn reports_json_objects_for_tooling() {
+        assert_eq!(
+            report("var = 1;", ReportFormat::Json),
+            [r#"{"line":1,"column":5,"severity":"error","message":"Expected variable name."}"#]
+        );
+    }
+}
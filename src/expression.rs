@@ -1,30 +1,248 @@
 use std::fmt::Display;
+use std::rc::Rc;
 
 use crate::{
-    token::{Literal, Token},
+    statement::FunctionDeclaration,
+    token::{Literal, Span, Token},
     util::AstPrinter,
 };
 
 #[derive(Clone)]
 pub enum Expression {
+    Assign {
+        name: Token,
+        value: Box<Expression>,
+    },
+
     Binary {
         left: Box<Expression>,
         operator: Token,
         right: Box<Expression>,
     },
 
+    Call {
+        callee: Box<Expression>,
+        /// Closing parenthesis of the argument list, used to locate runtime errors
+        paren: Token,
+        arguments: Vec<Expression>,
+    },
+
+    Comma {
+        expressions: Vec<Expression>,
+    },
+
+    Get {
+        object: Box<Expression>,
+        name: Token,
+    },
+
+    Index {
+        collection: Box<Expression>,
+        bracket: Token,
+        index: Box<Expression>,
+        span: Span,
+    },
+
     Grouping {
         expression: Box<Expression>,
+        /// Covers the parentheses as well as the expression inside them
+        span: Span,
+    },
+
+    IndexSet {
+        collection: Box<Expression>,
+        bracket: Token,
+        index: Box<Expression>,
+        value: Box<Expression>,
+    },
+
+    /// An anonymous function, such as `fun (a, b) { return a + b; }`. Its declaration is shared
+    /// so that evaluating it doesn't copy the body.
+    Lambda {
+        keyword: Token,
+        declaration: Rc<FunctionDeclaration>,
+        span: Span,
+    },
+
+    ListLiteral {
+        elements: Vec<Expression>,
+        span: Span,
     },
 
     Literal {
         value: Literal,
+        span: Span,
+    },
+
+    Logical {
+        left: Box<Expression>,
+        operator: Token,
+        right: Box<Expression>,
+    },
+
+    Set {
+        object: Box<Expression>,
+        name: Token,
+        value: Box<Expression>,
+    },
+
+    Super {
+        keyword: Token,
+        method: Token,
+    },
+
+    Ternary {
+        condition: Box<Expression>,
+        then_expr: Box<Expression>,
+        else_expr: Box<Expression>,
+    },
+
+    This {
+        keyword: Token,
     },
 
     Unary {
         operator: Token,
         right: Box<Expression>,
     },
+
+    Variable {
+        name: Token,
+    },
+}
+
+/// Operation over expressions, with a method for each kind of expression. `Expression::accept`
+/// picks the method for an expression, so a consumer of the syntax tree only needs to say what
+/// to do with each variant, recursing into operands with `accept` as needed.
+pub trait Visitor<T> {
+    fn visit_assign(&mut self, name: &Token, value: &Expression) -> T;
+    fn visit_binary(&mut self, left: &Expression, operator: &Token, right: &Expression) -> T;
+    fn visit_call(&mut self, callee: &Expression, paren: &Token, arguments: &[Expression]) -> T;
+    fn visit_comma(&mut self, expressions: &[Expression]) -> T;
+    fn visit_get(&mut self, object: &Expression, name: &Token) -> T;
+    fn visit_grouping(&mut self, expression: &Expression) -> T;
+    fn visit_index(&mut self, collection: &Expression, bracket: &Token, index: &Expression) -> T;
+    fn visit_index_set(
+        &mut self,
+        collection: &Expression,
+        bracket: &Token,
+        index: &Expression,
+        value: &Expression,
+    ) -> T;
+    fn visit_lambda(&mut self, keyword: &Token, declaration: &FunctionDeclaration) -> T;
+    fn visit_list_literal(&mut self, elements: &[Expression]) -> T;
+    fn visit_literal(&mut self, value: &Literal) -> T;
+    fn visit_logical(&mut self, left: &Expression, operator: &Token, right: &Expression) -> T;
+    fn visit_set(&mut self, object: &Expression, name: &Token, value: &Expression) -> T;
+    fn visit_super(&mut self, keyword: &Token, method: &Token) -> T;
+    fn visit_ternary(
+        &mut self,
+        condition: &Expression,
+        then_expr: &Expression,
+        else_expr: &Expression,
+    ) -> T;
+    fn visit_this(&mut self, keyword: &Token) -> T;
+    fn visit_unary(&mut self, operator: &Token, right: &Expression) -> T;
+    fn visit_variable(&mut self, name: &Token) -> T;
+}
+
+impl Expression {
+    /// Call the visitor method matching this expression's variant
+    pub fn accept<T, V: Visitor<T> + ?Sized>(&self, visitor: &mut V) -> T {
+        match self {
+            Expression::Assign { name, value } => visitor.visit_assign(name, value),
+            Expression::Binary {
+                left,
+                operator,
+                right,
+            } => visitor.visit_binary(left, operator, right),
+            Expression::Call {
+                callee,
+                paren,
+                arguments,
+            } => visitor.visit_call(callee, paren, arguments),
+            Expression::Comma { expressions } => visitor.visit_comma(expressions),
+            Expression::Get { object, name } => visitor.visit_get(object, name),
+            Expression::Grouping { expression, .. } => visitor.visit_grouping(expression),
+            Expression::Index {
+                collection,
+                bracket,
+                index,
+                ..
+            } => visitor.visit_index(collection, bracket, index),
+            Expression::IndexSet {
+                collection,
+                bracket,
+                index,
+                value,
+            } => visitor.visit_index_set(collection, bracket, index, value),
+            Expression::Lambda {
+                keyword,
+                declaration,
+                ..
+            } => visitor.visit_lambda(keyword, declaration),
+            Expression::ListLiteral { elements, .. } => visitor.visit_list_literal(elements),
+            Expression::Literal { value, .. } => visitor.visit_literal(value),
+            Expression::Logical {
+                left,
+                operator,
+                right,
+            } => visitor.visit_logical(left, operator, right),
+            Expression::Set {
+                object,
+                name,
+                value,
+            } => visitor.visit_set(object, name, value),
+            Expression::Super { keyword, method } => visitor.visit_super(keyword, method),
+            Expression::Ternary {
+                condition,
+                then_expr,
+                else_expr,
+            } => visitor.visit_ternary(condition, then_expr, else_expr),
+            Expression::This { keyword } => visitor.visit_this(keyword),
+            Expression::Unary { operator, right } => visitor.visit_unary(operator, right),
+            Expression::Variable { name } => visitor.visit_variable(name),
+        }
+    }
+}
+
+impl Expression {
+    /// The characters of source this expression was parsed from. Expressions which begin and end
+    /// with a token, or with operands of their own, work this out from those, while the rest
+    /// record their span when parsed.
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Assign { name, value } => name.span().to(value.span()),
+            Expression::Binary { left, right, .. } | Expression::Logical { left, right, .. } => {
+                left.span().to(right.span())
+            }
+            Expression::Call { callee, paren, .. } => callee.span().to(paren.span()),
+            Expression::Comma { expressions } => expressions
+                .iter()
+                .map(Expression::span)
+                .reduce(Span::to)
+                .expect("Comma expressions always have operands"),
+            Expression::Get { object, name } => object.span().to(name.span()),
+            Expression::Grouping { span, .. }
+            | Expression::Index { span, .. }
+            | Expression::Lambda { span, .. }
+            | Expression::ListLiteral { span, .. }
+            | Expression::Literal { span, .. } => *span,
+            Expression::IndexSet {
+                collection, value, ..
+            } => collection.span().to(value.span()),
+            Expression::Set { object, value, .. } => object.span().to(value.span()),
+            Expression::Super { keyword, method } => keyword.span().to(method.span()),
+            Expression::Ternary {
+                condition,
+                else_expr,
+                ..
+            } => condition.span().to(else_expr.span()),
+            Expression::This { keyword } => keyword.span(),
+            Expression::Unary { operator, right } => operator.span().to(right.span()),
+            Expression::Variable { name } => name.span(),
+        }
+    }
 }
 
 impl Display for Expression {
@@ -32,3 +250,174 @@ impl Display for Expression {
         write!(f, "{}", Expression::format_ast(self))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Expression, FunctionDeclaration, Visitor};
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::statement::Statement;
+    use crate::token::{Literal, Span, Token};
+
+    /// Example visitor which lists the variables an expression reads, in the order it reads them
+    struct ReadVariables;
+
+    impl ReadVariables {
+        fn all<'a>(
+            &mut self,
+            expressions: impl IntoIterator<Item = &'a Expression>,
+        ) -> Vec<String> {
+            expressions
+                .into_iter()
+                .flat_map(|expression| expression.accept(self))
+                .collect()
+        }
+    }
+
+    impl Visitor<Vec<String>> for ReadVariables {
+        fn visit_assign(&mut self, _: &Token, value: &Expression) -> Vec<String> {
+            value.accept(self)
+        }
+
+        fn visit_binary(
+            &mut self,
+            left: &Expression,
+            _: &Token,
+            right: &Expression,
+        ) -> Vec<String> {
+            self.all([left, right])
+        }
+
+        fn visit_call(
+            &mut self,
+            callee: &Expression,
+            _: &Token,
+            arguments: &[Expression],
+        ) -> Vec<String> {
+            self.all(std::iter::once(callee).chain(arguments))
+        }
+
+        fn visit_comma(&mut self, expressions: &[Expression]) -> Vec<String> {
+            self.all(expressions)
+        }
+
+        fn visit_get(&mut self, object: &Expression, _: &Token) -> Vec<String> {
+            object.accept(self)
+        }
+
+        fn visit_grouping(&mut self, expression: &Expression) -> Vec<String> {
+            expression.accept(self)
+        }
+
+        fn visit_index(
+            &mut self,
+            collection: &Expression,
+            _: &Token,
+            index: &Expression,
+        ) -> Vec<String> {
+            self.all([collection, index])
+        }
+
+        fn visit_index_set(
+            &mut self,
+            collection: &Expression,
+            _: &Token,
+            index: &Expression,
+            value: &Expression,
+        ) -> Vec<String> {
+            self.all([collection, index, value])
+        }
+
+        fn visit_lambda(&mut self, _: &Token, _: &FunctionDeclaration) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn visit_list_literal(&mut self, elements: &[Expression]) -> Vec<String> {
+            self.all(elements)
+        }
+
+        fn visit_literal(&mut self, _: &Literal) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn visit_logical(
+            &mut self,
+            left: &Expression,
+            _: &Token,
+            right: &Expression,
+        ) -> Vec<String> {
+            self.all([left, right])
+        }
+
+        fn visit_set(&mut self, object: &Expression, _: &Token, value: &Expression) -> Vec<String> {
+            self.all([object, value])
+        }
+
+        fn visit_super(&mut self, _: &Token, _: &Token) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn visit_ternary(
+            &mut self,
+            condition: &Expression,
+            then_expr: &Expression,
+            else_expr: &Expression,
+        ) -> Vec<String> {
+            self.all([condition, then_expr, else_expr])
+        }
+
+        fn visit_this(&mut self, _: &Token) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn visit_unary(&mut self, _: &Token, right: &Expression) -> Vec<String> {
+            right.accept(self)
+        }
+
+        fn visit_variable(&mut self, name: &Token) -> Vec<String> {
+            vec![name.lexeme.clone()]
+        }
+    }
+
+    /// Parse a single expression statement and return its expression
+    fn expression(source: &str) -> Expression {
+        let tokens = Scanner::new(source.to_string())
+            .scan_tokens()
+            .expect("source should scan");
+        match Parser::new(tokens).parse().ok().and_then(|mut s| s.pop()) {
+            Some(Statement::Expression(expression)) => expression,
+            _ => panic!("Expected an expression statement"),
+        }
+    }
+
+    fn read_variables(source: &str) -> Vec<String> {
+        expression(source).accept(&mut ReadVariables)
+    }
+
+    #[test]
+    fn visitors_recurse_through_operands() {
+        assert_eq!(read_variables("a + -b * (c - 1);"), ["a", "b", "c"]);
+        assert_eq!(
+            read_variables("x = f(y, z[i]) ? p.q : [r];"),
+            ["f", "y", "z", "i", "p", "r"]
+        );
+        assert_eq!(read_variables("1 + 2;"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn groupings_span_their_parentheses() {
+        let Expression::Binary { right, .. } = expression("x * (1 +\n 2);") else {
+            panic!("Expected a binary expression");
+        };
+        assert_eq!(
+            right.span(),
+            Span {
+                start: 4,
+                end: 12,
+                line: 1,
+                column: 5
+            }
+        );
+        assert_eq!(expression("x * (1 +\n 2);").span().start, 0);
+    }
+}
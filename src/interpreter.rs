@@ -1,177 +1,1352 @@
-use std::fmt::Display;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
 
-use crate::error::{runtime_error, RuntimeError, RuntimeResult};
+use crate::builtins::define_builtins;
+use crate::callable::{LoxCallable, LoxFunction};
+use crate::class::{LoxClass, LoxInstance};
+use crate::environment::Environment;
+use crate::error::{ExecuteResult, RuntimeError, RuntimeErrorKind, RuntimeResult, Unwind};
 use crate::expression::Expression;
-use crate::token::{Literal, Token, TokenType};
+use crate::statement::{self, FunctionDeclaration, Statement};
+use crate::symbol::Symbol;
+use crate::token::{Literal, Token, TokenId, TokenType};
+use crate::value::Value;
 
-pub struct Interpreter;
+/// Default limit on how deeply calls may nest before a Lox program is stopped, well before the
+/// Rust stack that the interpreter recurses on would overflow
+const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// Longest string, in bytes, that repeating a string with `*` may build, so that a huge count is
+/// reported as an error rather than exhausting memory
+const MAX_REPEATED_STRING_LENGTH: usize = 1 << 30;
+
+/// Callback given each value printed by a `print` statement, see [`Interpreter::set_on_print`]
+pub type PrintCallback = Box<dyn FnMut(&Value)>;
+
+pub struct Interpreter {
+    globals: Rc<RefCell<Environment>>,
+    /// The innermost scope at the current point of execution
+    environment: Rc<RefCell<Environment>>,
+    /// Number of scopes between each local variable use and its binding, filled in by the
+    /// resolver. Names missing from here are globals. Uses are told apart by their token's id,
+    /// as positions repeat between lines of REPL input.
+    locals: HashMap<TokenId, usize>,
+    /// Number of calls currently in progress
+    call_depth: usize,
+    max_call_depth: usize,
+    /// Most times the body of any one loop may run each time the loop is entered, if limited
+    max_loop_iterations: Option<usize>,
+    /// Whether variables declared without an initialiser start out as `nil`, rather than being
+    /// an error to read until assigned
+    implicit_nil: bool,
+    /// Whether `+` converts any value to a string when the other operand is a string
+    lenient_concatenation: bool,
+    /// Largest difference between two numbers that `==` treats as equal, if any
+    equality_epsilon: Option<f64>,
+    /// Where `print` statements and echoed REPL values are written
+    output: Box<dyn Write>,
+    /// Called with each value printed by a `print` statement, before it is written to `output`
+    on_print: Option<PrintCallback>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Interpreter {
     pub fn new() -> Self {
-        Interpreter {}
+        Self::with_output(Box::new(io::stdout()))
     }
 
-    pub fn interpret(&self, expression: Expression) {
-        let value = Self::evaluate(expression);
-        match value {
-            Ok(value) => println!("{}", value),
-            Err(error) => runtime_error(error),
+    /// Create an interpreter which writes program output to the given sink rather than stdout.
+    /// To read the output back afterwards, pass a writer which shares its buffer with the caller.
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        let mut globals = Environment::new();
+        define_builtins(&mut globals);
+
+        let globals = Rc::new(RefCell::new(globals));
+        Interpreter {
+            environment: Rc::clone(&globals),
+            globals,
+            locals: HashMap::new(),
+            call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            max_loop_iterations: None,
+            implicit_nil: true,
+            lenient_concatenation: false,
+            equality_epsilon: None,
+            output,
+            on_print: None,
         }
     }
 
-    fn evaluate(expression: Expression) -> RuntimeResult<Value> {
+    /// Set how deeply calls may nest before a "Stack overflow." runtime error is reported
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.max_call_depth = max_call_depth;
+    }
+
+    /// Limit how many times the body of a loop may run before a "Loop exceeded N iterations."
+    /// runtime error is reported, so that runaway loops end. Loops are unlimited by default.
+    pub fn set_max_loop_iterations(&mut self, max_loop_iterations: Option<usize>) {
+        self.max_loop_iterations = max_loop_iterations;
+    }
+
+    /// Choose whether `var x;` sets `x` to `nil`, as it does by default. Otherwise, reading `x`
+    /// before assigning to it is a "Variable 'x' used before assignment." runtime error.
+    pub fn set_implicit_nil(&mut self, implicit_nil: bool) {
+        self.implicit_nil = implicit_nil;
+    }
+
+    /// Allow `+` to join a string with a value of any other type, such as `"count: " + 5` or
+    /// `5 + " apples"`, by converting the other value as `print` would. By default, adding a
+    /// string to anything but another string is an error.
+    pub fn set_lenient_concatenation(&mut self, lenient_concatenation: bool) {
+        self.lenient_concatenation = lenient_concatenation;
+    }
+
+    /// Make `==` and `!=` treat numbers as equal when they differ by no more than `epsilon`, so
+    /// that `0.1 + 0.2 == 0.3` is true. By default, numbers are compared exactly.
+    pub fn set_equality_epsilon(&mut self, epsilon: Option<f64>) {
+        self.equality_epsilon = epsilon;
+    }
+
+    /// Call `on_print` with each value printed by a `print` statement, so that an embedder can capture
+    /// printed values with their types intact. Values are still written to the output as well,
+    /// which can be discarded by creating the interpreter with `with_output(Box::new(io::sink()))`.
+    pub fn set_on_print(&mut self, on_print: PrintCallback) {
+        self.on_print = Some(on_print);
+    }
+
+    /// Every global variable, including native functions, sorted by name
+    pub fn globals(&self) -> Vec<(String, Option<Value>)> {
+        self.globals.borrow().bindings()
+    }
+
+    /// Record that the variable use `name` refers to a binding `depth` scopes out
+    pub(crate) fn resolve(&mut self, name: &Token, depth: usize) {
+        self.locals.insert(name.id, depth);
+    }
+
+    /// Execute the statements in order, returning the value of each top-level expression
+    /// statement
+    pub fn interpret(&mut self, statements: Vec<Statement>) -> RuntimeResult<Vec<Value>> {
+        let mut values = Vec::new();
+
+        for statement in statements {
+            match statement {
+                Statement::Expression(expression) => values.push(self.evaluate(&expression)?),
+                statement => match self.execute(&statement) {
+                    Ok(()) => {}
+                    Err(Unwind::Error(error)) => return Err(error),
+                    // Returning outside of a function ends the program
+                    Err(Unwind::Return(_)) => break,
+                },
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// Interpret a line of REPL input. A line consisting of a single expression statement has
+    /// its value printed, rather than being silently discarded.
+    pub fn interpret_repl(&mut self, statements: Vec<Statement>) -> RuntimeResult<()> {
+        let is_single_expression = matches!(statements.as_slice(), [Statement::Expression(_)]);
+
+        let values = self.interpret(statements)?;
+        if is_single_expression {
+            for value in values {
+                self.print(&value);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn execute(&mut self, statement: &Statement) -> ExecuteResult<()> {
+        statement.accept(self)
+    }
+
+    /// Execute the statements in the given scope, restoring the current scope afterwards even if
+    /// a statement fails
+    pub(crate) fn execute_block(
+        &mut self,
+        statements: &[Statement],
+        environment: Environment,
+    ) -> ExecuteResult<()> {
+        let previous = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.execute(statement));
+
+        self.environment = previous;
+        result
+    }
+
+    fn evaluate(&mut self, expression: &Expression) -> RuntimeResult<Value> {
         match expression {
+            Expression::Assign { name, value } => {
+                let value = self.evaluate(value)?;
+                match self.locals.get(&name.id) {
+                    Some(depth) => {
+                        self.environment
+                            .borrow_mut()
+                            .assign_at(*depth, name, value.clone())?
+                    }
+                    None => self.globals.borrow_mut().assign(name, value.clone())?,
+                }
+                Ok(value)
+            }
             Expression::Binary {
                 left,
                 operator,
                 right,
             } => {
-                let left = Self::evaluate(*left)?;
-                let right = Self::evaluate(*right)?;
-
-                match operator.token_type {
-                    // Arithmetic
-                    TokenType::Minus => {
-                        let (l_num, r_num) = Self::check_number_operands(operator, left, right)?;
-                        Ok(Value::Number(l_num - r_num))
-                    }
-                    TokenType::Slash => {
-                        let (l_num, r_num) =
-                            Self::check_number_operands(operator.clone(), left, right)?;
-                        if r_num == 0.0 {
-                            Err(RuntimeError::new("Division by zero".to_string(), operator))
-                        } else {
-                            Ok(Value::Number(l_num / r_num))
-                        }
-                    }
-                    TokenType::Star => {
-                        let (l_num, r_num) = Self::check_number_operands(operator, left, right)?;
-                        Ok(Value::Number(l_num * r_num))
-                    }
-                    TokenType::Plus => match (&left, &right) {
-                        (Value::Number(left_num), Value::Number(right_num)) => {
-                            Ok(Value::Number(left_num + right_num))
-                        }
-                        (Value::String(left_str), Value::String(right_str)) => {
-                            Ok(Value::String(format!("{}{}", left_str, right_str)))
-                        }
-                        (Value::String(left_str), Value::Number(right_num)) => {
-                            Ok(Value::String(format!("{}{}", left_str, right_num)))
-                        }
-                        _ => Err(RuntimeError::new(
-                            format!(
-                                "Operands '{}' and '{}' must both be numbers or strings.",
-                                left, right,
-                            ),
-                            operator,
-                        )),
-                    },
+                // Errors in applying the operator are about the expression as a whole
+                let span = left.span().to(right.span());
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
 
-                    // Comparison
-                    TokenType::Greater => {
-                        let (l_num, r_num) = Self::check_number_operands(operator, left, right)?;
-                        Ok(Value::Boolean(l_num > r_num))
-                    }
-                    TokenType::GreaterEqual => {
-                        let (l_num, r_num) = Self::check_number_operands(operator, left, right)?;
-                        Ok(Value::Boolean(l_num >= r_num))
-                    }
-                    TokenType::Less => {
-                        let (l_num, r_num) = Self::check_number_operands(operator, left, right)?;
-                        Ok(Value::Boolean(l_num < r_num))
-                    }
-                    TokenType::LessEqual => {
-                        let (l_num, r_num) = Self::check_number_operands(operator, left, right)?;
-                        Ok(Value::Boolean(l_num <= r_num))
+                self.apply_binary(operator, left, right)
+                    .map_err(|error| error.with_span(span))
+            }
+            Expression::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                let callee = self.evaluate(callee)?;
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.evaluate(argument))
+                    .collect::<RuntimeResult<Vec<Value>>>()?;
+
+                let function: Rc<dyn LoxCallable> = match callee {
+                    Value::Callable(function) => function,
+                    Value::Class(class) => class,
+                    _ => {
+                        return Err(RuntimeError::new(
+                            RuntimeErrorKind::NotCallable,
+                            "Can only call functions and classes.".to_string(),
+                            paren,
+                        ))
                     }
+                };
 
-                    // Equality
-                    TokenType::BangEqual => Ok(Value::Boolean(left != right)),
-                    TokenType::EqualEqual => Ok(Value::Boolean(left == right)),
+                if arguments.len() != function.arity() {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::Arity,
+                        format!(
+                            "Expected {} arguments but got {}.",
+                            function.arity(),
+                            arguments.len()
+                        ),
+                        paren,
+                    ));
+                }
 
-                    _ => unreachable!(
-                        "Operator '{}' was not handled as a binary expression",
-                        operator
-                    ),
+                if self.call_depth >= self.max_call_depth {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::StackOverflow,
+                        "Stack overflow.".to_string(),
+                        paren,
+                    ));
                 }
+
+                self.call_depth += 1;
+                let result = function.call(self, arguments, paren);
+                self.call_depth -= 1;
+                result
             }
-            Expression::Grouping { expression } => Self::evaluate(*expression),
-            Expression::Literal { value } => match value {
-                Literal::String(str) => Ok(Value::String(str)),
-                Literal::Number(num) => Ok(Value::Number(num)),
-                Literal::Boolean(bool) => Ok(Value::Boolean(bool)),
-                Literal::None => Ok(Value::Nil),
+            Expression::Comma { expressions } => {
+                // Every expression is evaluated for its side effects, but only the last is kept
+                let mut value = Value::Nil;
+                for expression in expressions {
+                    value = self.evaluate(expression)?;
+                }
+                Ok(value)
+            }
+            Expression::Get { object, name } => match self.evaluate(object)? {
+                Value::Instance(instance) => LoxInstance::get(&instance, name),
+                _ => Err(RuntimeError::new(
+                    RuntimeErrorKind::TypeMismatch,
+                    "Only instances have properties.".to_string(),
+                    name,
+                )),
             },
-            Expression::Unary { operator, right } => {
-                let right_val = Self::evaluate(*right)?;
-                match operator.token_type {
-                    TokenType::Bang => Ok(Value::Boolean(!right_val.is_truthy())),
-                    TokenType::Minus => {
-                        if let Value::Number(num) = right_val {
-                            Ok(Value::Number(-num))
-                        } else {
-                            Err(RuntimeError::new(
-                                format!(
-                                    "Operand '{}' must be a number to apply '{}' operator",
-                                    right_val, operator
-                                ),
-                                operator,
-                            ))
-                        }
-                    }
+            Expression::Grouping { expression, .. } => self.evaluate(expression),
+            Expression::Index {
+                collection,
+                bracket,
+                index,
+                ..
+            } => {
+                let collection = self.evaluate(collection)?;
+                let index = self.evaluate(index)?;
+
+                let (elements, position) = Self::check_list_index(bracket, collection, &index)?;
+                let element = elements.borrow()[position].clone();
+                Ok(element)
+            }
+            Expression::IndexSet {
+                collection,
+                bracket,
+                index,
+                value,
+            } => {
+                let collection = self.evaluate(collection)?;
+                let index = self.evaluate(index)?;
+                let value = self.evaluate(value)?;
+
+                // Only existing elements can be replaced, the list never grows
+                let (elements, position) = Self::check_list_index(bracket, collection, &index)?;
+                elements.borrow_mut()[position] = value.clone();
+                Ok(value)
+            }
+            Expression::Lambda { declaration, .. } => {
+                // Like a declared function, it captures the scope it is evaluated in
+                let function =
+                    LoxFunction::new(Rc::clone(declaration), Rc::clone(&self.environment), false);
+                Ok(Value::Callable(Rc::new(function)))
+            }
+            Expression::ListLiteral { elements, .. } => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.evaluate(element))
+                    .collect::<RuntimeResult<Vec<Value>>>()?;
+                Ok(Value::List(Rc::new(RefCell::new(elements))))
+            }
+            Expression::Literal { value, .. } => Ok(Value::from(value.clone())),
+            Expression::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.evaluate(left)?;
+
+                // Skip the right operand if the left one alone decides the result
+                let short_circuits = match operator.token_type {
+                    TokenType::Or => left.is_truthy(),
+                    TokenType::And => !left.is_truthy(),
                     _ => unreachable!(
-                        "Operator '{}' was not handled as a unary expression",
+                        "Operator '{}' was not handled as a logical expression",
                         operator
                     ),
+                };
+
+                if short_circuits {
+                    Ok(left)
+                } else {
+                    self.evaluate(right)
+                }
+            }
+            Expression::Set {
+                object,
+                name,
+                value,
+            } => {
+                let Value::Instance(instance) = self.evaluate(object)? else {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::TypeMismatch,
+                        "Only instances have fields.".to_string(),
+                        name,
+                    ));
+                };
+
+                let value = self.evaluate(value)?;
+                instance.borrow_mut().set(name, value.clone());
+                Ok(value)
+            }
+            Expression::Super { keyword, method } => {
+                let distance = *self
+                    .locals
+                    .get(&keyword.id)
+                    .expect("'super' was not resolved to a local scope");
+                let Value::Class(superclass) =
+                    self.environment.borrow().get_at(distance, keyword)?
+                else {
+                    unreachable!("'super' was not bound to a class");
+                };
+
+                // `this` is always bound in the scope just inside the one binding `super`
+                let this = Token::new(
+                    TokenType::This,
+                    "this".to_string(),
+                    Literal::None,
+                    keyword.line,
+                    keyword.column,
+                    keyword.offset,
+                );
+                let instance = self.environment.borrow().get_at(distance - 1, &this)?;
+
+                match superclass.find_method(method.symbol()) {
+                    Some(function) => Ok(Value::Callable(Rc::new(function.bind(instance)))),
+                    None => Err(RuntimeError::new(
+                        RuntimeErrorKind::UndefinedProperty,
+                        format!("Undefined property '{}'.", method.lexeme),
+                        method,
+                    )),
+                }
+            }
+            Expression::Ternary {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                if self.evaluate(condition)?.is_truthy() {
+                    self.evaluate(then_expr)
+                } else {
+                    self.evaluate(else_expr)
                 }
             }
+            Expression::This { keyword } => self.look_up_variable(keyword),
+            Expression::Unary { operator, right } => {
+                let span = operator.span().to(right.span());
+                let right = self.evaluate(right)?;
+                Self::unary_operation(operator, right).map_err(|error| error.with_span(span))
+            }
+            Expression::Variable { name } => self.look_up_variable(name),
+        }
+    }
+
+    fn print(&mut self, value: &Value) {
+        writeln!(self.output, "{}", value).expect("Failed to write program output");
+    }
+
+    fn look_up_variable(&self, name: &Token) -> RuntimeResult<Value> {
+        match self.locals.get(&name.id) {
+            Some(depth) => self.environment.borrow().get_at(*depth, name),
+            None => self.globals.borrow().get(name),
+        }
+    }
+
+    /// Check that `collection` is a list and `index` is the position of one of its elements,
+    /// returning the list and the position
+    fn check_list_index(
+        bracket: &Token,
+        collection: Value,
+        index: &Value,
+    ) -> RuntimeResult<(Rc<RefCell<Vec<Value>>>, usize)> {
+        let Value::List(elements) = collection else {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::TypeMismatch,
+                format!("Can only index into lists, not '{}'.", collection),
+                bracket,
+            ));
+        };
+        if !index.is_integer() {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::TypeMismatch,
+                format!("List index '{}' must be an integer.", index),
+                bracket,
+            ));
+        }
+
+        let length = elements.borrow().len();
+        let position = index.as_number().unwrap_or_default();
+        if position < 0.0 || position >= length as f64 {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::IndexOutOfRange,
+                format!(
+                    "List index {} is out of range for a list of length {}.",
+                    index, length
+                ),
+                bracket,
+            ));
+        }
+
+        Ok((elements, position as usize))
+    }
+
+    /// Equality as tested by `==`, which may allow numbers to differ slightly
+    fn is_equal(left: &Value, right: &Value, epsilon: Option<f64>) -> bool {
+        match (left, right, epsilon) {
+            // Infinities are only equal to themselves, as their difference is not a number
+            (Value::Number(l_num), Value::Number(r_num), Some(epsilon)) => {
+                l_num == r_num || (l_num - r_num).abs() <= epsilon
+            }
+            _ => left == right,
         }
     }
 
     fn check_number_operands(
-        operator: Token,
+        operator: &Token,
         left: Value,
         right: Value,
     ) -> RuntimeResult<(f64, f64)> {
-        match (&left, &right) {
-            (Value::Number(left_num), Value::Number(right_num)) => Ok((*left_num, *right_num)),
+        match (left.as_number(), right.as_number()) {
+            (Some(left_num), Some(right_num)) => Ok((left_num, right_num)),
             _ => Err(RuntimeError::new(
+                RuntimeErrorKind::TypeMismatch,
                 format!("Operands '{}' and '{}' must both be numbers.", left, right),
                 operator,
             )),
         }
     }
-}
 
-#[derive(PartialEq)]
-enum Value {
-    String(String),
-    Number(f64),
-    Boolean(bool),
-    Nil,
+    /// Apply a binary operator as configured for this interpreter
+    pub(crate) fn apply_binary(
+        &self,
+        operator: &Token,
+        left: Value,
+        right: Value,
+    ) -> RuntimeResult<Value> {
+        Self::binary_operation(
+            operator,
+            left,
+            right,
+            self.lenient_concatenation,
+            self.equality_epsilon,
+        )
+    }
+
+    /// Apply a unary operator to an operand that has already been evaluated
+    pub(crate) fn unary_operation(operator: &Token, right: Value) -> RuntimeResult<Value> {
+        match operator.token_type {
+            TokenType::Bang => Ok(Value::from(!right.is_truthy())),
+            TokenType::Minus => match right.as_number() {
+                Some(num) => Ok(Value::Number(-num)),
+                None => Err(RuntimeError::new(
+                    RuntimeErrorKind::TypeMismatch,
+                    format!(
+                        "Operand '{}' must be a number to apply '{}' operator",
+                        right, operator
+                    ),
+                    operator,
+                )),
+            },
+            _ => unreachable!(
+                "Operator '{}' was not handled as a unary expression",
+                operator
+            ),
+        }
+    }
+
+    /// Apply a binary operator to operands that have already been evaluated. This is shared with
+    /// the bytecode VM so that both backends agree on the semantics of every operator.
+    pub(crate) fn binary_operation(
+        operator: &Token,
+        left: Value,
+        right: Value,
+        lenient_concatenation: bool,
+        equality_epsilon: Option<f64>,
+    ) -> RuntimeResult<Value> {
+        match operator.token_type {
+            // Arithmetic
+            TokenType::Minus => {
+                let (l_num, r_num) = Self::check_number_operands(operator, left, right)?;
+                Ok(Value::Number(l_num - r_num))
+            }
+            TokenType::Slash => {
+                let (l_num, r_num) = Self::check_number_operands(operator, left, right)?;
+                if r_num == 0.0 {
+                    Err(RuntimeError::new(
+                        RuntimeErrorKind::DivisionByZero,
+                        "Division by zero.".to_string(),
+                        operator,
+                    ))
+                } else {
+                    Ok(Value::Number(l_num / r_num))
+                }
+            }
+            TokenType::Star => match (&left, &right) {
+                // Strings can be repeated by a count on either side, as in `"ab" * 3`
+                (Value::String(str), Value::Number(count))
+                | (Value::Number(count), Value::String(str)) => {
+                    if *count < 0.0 || count.fract() != 0.0 {
+                        return Err(RuntimeError::new(
+                            RuntimeErrorKind::TypeMismatch,
+                            format!(
+                                "String repetition count '{}' must be a non-negative integer.",
+                                Value::Number(*count)
+                            ),
+                            operator,
+                        ));
+                    }
+                    // Counts too large for `usize` saturate, so their length overflows too
+                    let count = *count as usize;
+                    match str.len().checked_mul(count) {
+                        Some(length) if length <= MAX_REPEATED_STRING_LENGTH => {
+                            Ok(Value::String(str.repeat(count).into()))
+                        }
+                        _ => Err(RuntimeError::new(
+                            RuntimeErrorKind::StringTooLong,
+                            format!(
+                                "String repetition would be longer than {} bytes.",
+                                MAX_REPEATED_STRING_LENGTH
+                            ),
+                            operator,
+                        )),
+                    }
+                }
+                _ => {
+                    let (l_num, r_num) = Self::check_number_operands(operator, left, right)?;
+                    Ok(Value::Number(l_num * r_num))
+                }
+            },
+            TokenType::StarStar => {
+                let (l_num, r_num) = Self::check_number_operands(operator, left, right)?;
+                Ok(Value::Number(l_num.powf(r_num)))
+            }
+            TokenType::Percent => {
+                let (l_num, r_num) = Self::check_number_operands(operator, left, right)?;
+                if r_num == 0.0 {
+                    Err(RuntimeError::new(
+                        RuntimeErrorKind::DivisionByZero,
+                        "Division by zero.".to_string(),
+                        operator,
+                    ))
+                } else {
+                    Ok(Value::Number(l_num % r_num))
+                }
+            }
+            TokenType::Plus => match (&left, &right) {
+                (Value::Number(left_num), Value::Number(right_num)) => {
+                    Ok(Value::Number(left_num + right_num))
+                }
+                (Value::String(left_str), Value::String(right_str)) => {
+                    Ok(Value::String(format!("{}{}", left_str, right_str).into()))
+                }
+                (Value::String(_), _) | (_, Value::String(_)) if lenient_concatenation => {
+                    Ok(Value::String(format!("{}{}", left, right).into()))
+                }
+                _ => Err(RuntimeError::new(
+                    RuntimeErrorKind::TypeMismatch,
+                    format!(
+                        "Operands '{}' and '{}' must both be numbers or strings.",
+                        left, right,
+                    ),
+                    operator,
+                )),
+            },
+
+            // Comparison
+            TokenType::Greater => {
+                let (l_num, r_num) = Self::check_number_operands(operator, left, right)?;
+                Ok(Value::from(l_num > r_num))
+            }
+            TokenType::GreaterEqual => {
+                let (l_num, r_num) = Self::check_number_operands(operator, left, right)?;
+                Ok(Value::from(l_num >= r_num))
+            }
+            TokenType::Less => {
+                let (l_num, r_num) = Self::check_number_operands(operator, left, right)?;
+                Ok(Value::from(l_num < r_num))
+            }
+            TokenType::LessEqual => {
+                let (l_num, r_num) = Self::check_number_operands(operator, left, right)?;
+                Ok(Value::from(l_num <= r_num))
+            }
+
+            // Equality
+            TokenType::BangEqual => Ok(Value::from(!Self::is_equal(
+                &left,
+                &right,
+                equality_epsilon,
+            ))),
+            TokenType::EqualEqual => {
+                Ok(Value::from(Self::is_equal(&left, &right, equality_epsilon)))
+            }
+
+            _ => unreachable!(
+                "Operator '{}' was not handled as a binary expression",
+                operator
+            ),
+        }
+    }
 }
 
-impl Value {
-    fn is_truthy(&self) -> bool {
-        match self {
-            Value::String(_) | Value::Number(_) => false,
-            Value::Boolean(bool) => !bool,
-            Value::Nil => false,
+impl statement::Visitor<ExecuteResult<()>> for Interpreter {
+    fn visit_block(&mut self, statements: &[Statement]) -> ExecuteResult<()> {
+        let environment = Environment::with_enclosing(Rc::clone(&self.environment));
+        self.execute_block(statements, environment)
+    }
+
+    fn visit_class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expression>,
+        methods: &[Rc<FunctionDeclaration>],
+    ) -> ExecuteResult<()> {
+        let superclass = match superclass {
+            Some(expression) => {
+                let Expression::Variable {
+                    name: superclass_name,
+                } = expression
+                else {
+                    unreachable!("Superclass was not parsed as a variable expression");
+                };
+
+                match self.evaluate(expression)? {
+                    Value::Class(class) => Some(class),
+                    _ => {
+                        return Err(RuntimeError::new(
+                            RuntimeErrorKind::TypeMismatch,
+                            "Superclass must be a class.".to_string(),
+                            superclass_name,
+                        )
+                        .into())
+                    }
+                }
+            }
+            None => None,
+        };
+
+        // Methods of a subclass close over an extra scope which binds `super`
+        let previous = superclass.as_ref().map(|superclass| {
+            let mut environment = Environment::with_enclosing(Rc::clone(&self.environment));
+            environment.define(Symbol::intern("super"), Value::Class(Rc::clone(superclass)));
+            std::mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)))
+        });
+
+        let methods = methods
+            .iter()
+            .map(|method| {
+                let name = method.declared_name();
+                let is_initializer = name.lexeme == "init";
+                let function = LoxFunction::new(
+                    Rc::clone(method),
+                    Rc::clone(&self.environment),
+                    is_initializer,
+                );
+                (name.symbol(), Rc::new(function))
+            })
+            .collect();
+
+        if let Some(previous) = previous {
+            self.environment = previous;
         }
+
+        let class = LoxClass::new(name.lexeme.clone(), superclass, methods);
+        self.environment
+            .borrow_mut()
+            .define(name.symbol(), Value::Class(Rc::new(class)));
+        Ok(())
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) -> ExecuteResult<()> {
+        self.evaluate(expression)?;
+        Ok(())
+    }
+
+    fn visit_function(&mut self, declaration: &Rc<FunctionDeclaration>) -> ExecuteResult<()> {
+        // Functions capture the scope they are declared in as their closure
+        let function =
+            LoxFunction::new(Rc::clone(declaration), Rc::clone(&self.environment), false);
+        self.environment.borrow_mut().define(
+            declaration.declared_name().symbol(),
+            Value::Callable(Rc::new(function)),
+        );
+        Ok(())
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expression,
+        then_branch: &Statement,
+        else_branch: Option<&Statement>,
+    ) -> ExecuteResult<()> {
+        if self.evaluate(condition)?.is_truthy() {
+            self.execute(then_branch)
+        } else if let Some(else_branch) = else_branch {
+            self.execute(else_branch)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn visit_print(&mut self, _: &Token, expressions: &[Expression]) -> ExecuteResult<()> {
+        let values = expressions
+            .iter()
+            .map(|expression| self.evaluate(expression))
+            .collect::<RuntimeResult<Vec<_>>>()?;
+
+        if let Some(on_print) = &mut self.on_print {
+            for value in &values {
+                on_print(value);
+            }
+        }
+
+        let line = values
+            .iter()
+            .map(Value::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(self.output, "{}", line).expect("Failed to write program output");
+        Ok(())
+    }
+
+    fn visit_return(&mut self, _: &Token, value: Option<&Expression>) -> ExecuteResult<()> {
+        let value = match value {
+            Some(expression) => self.evaluate(expression)?,
+            None => Value::Nil,
+        };
+        Err(Unwind::Return(value))
+    }
+
+    fn visit_var(&mut self, name: &Token, initializer: Option<&Expression>) -> ExecuteResult<()> {
+        // Variables without an initialiser default to nil, unless that has been turned off
+        let value = match initializer {
+            Some(expression) => self.evaluate(expression)?,
+            None if self.implicit_nil => Value::Nil,
+            None => {
+                self.environment.borrow_mut().declare(name.symbol());
+                return Ok(());
+            }
+        };
+        self.environment.borrow_mut().define(name.symbol(), value);
+        Ok(())
+    }
+
+    fn visit_while(
+        &mut self,
+        keyword: &Token,
+        condition: &Expression,
+        body: &Statement,
+    ) -> ExecuteResult<()> {
+        let mut iterations = 0;
+        while self.evaluate(condition)?.is_truthy() {
+            if let Some(max) = self.max_loop_iterations {
+                if iterations >= max {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::IterationLimit,
+                        format!("Loop exceeded {max} iterations."),
+                        keyword,
+                    )
+                    .into());
+                }
+            }
+            iterations += 1;
+
+            self.execute(body)?;
+        }
+        Ok(())
     }
 }
 
-impl Display for Value {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Self::String(str) => str.to_string(),
-                Self::Number(num) => num.to_string(),
-                Self::Boolean(bool) => bool.to_string(),
-                Self::Nil => "nil".to_string(),
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::Interpreter;
+    use crate::error::{RuntimeErrorKind, RuntimeResult};
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+    use crate::scanner::Scanner;
+    use crate::test_util::{capturing_interpreter, errors_of, on_large_stack, output_of, run_with};
+    use crate::{parse_source, run_source, Value};
+
+    /// Scan, parse and resolve a program which is expected to be valid, then interpret it
+    fn interpret(interpreter: &mut Interpreter, source: &str) -> RuntimeResult<Vec<Value>> {
+        let tokens = Scanner::new(source.to_string())
+            .scan_tokens()
+            .expect("source should scan");
+        let statements = Parser::new(tokens).parse().expect("source should parse");
+        Resolver::new(interpreter)
+            .resolve(&statements)
+            .expect("source should resolve");
+        interpreter.interpret(statements)
+    }
+
+    fn evaluate(source: &str) -> Result<String, String> {
+        evaluate_with(&mut Interpreter::new(), source)
+    }
+
+    /// Run a program ending in an expression and return the value of that expression
+    fn evaluate_with(interpreter: &mut Interpreter, source: &str) -> Result<String, String> {
+        let values =
+            interpret(interpreter, &format!("{source};")).map_err(|error| error.message)?;
+        Ok(values
+            .last()
+            .expect("source should end with an expression")
+            .to_string())
+    }
+
+    #[test]
+    fn computes_remainders() {
+        assert_eq!(evaluate("5 % 3"), Ok("2".to_string()));
+        assert_eq!(evaluate("-5 % 3"), Ok("-2".to_string()));
+        assert_eq!(evaluate("5.5 % 2"), Ok("1.5".to_string()));
+        assert_eq!(evaluate("1 + 7 % 4 * 2"), Ok("7".to_string()));
+    }
+
+    #[test]
+    fn rejects_remainders_of_non_numbers() {
+        assert_eq!(
+            evaluate(r#"5 % "a""#),
+            Err("Operands '5' and 'a' must both be numbers.".to_string())
+        );
+        assert_eq!(
+            evaluate("nil % 2"),
+            Err("Operands 'nil' and '2' must both be numbers.".to_string())
+        );
+    }
+
+    #[test]
+    fn evaluates_variable_references() {
+        assert_eq!(evaluate("var a = 1; a + 2"), Ok("3".to_string()));
+    }
+
+    #[test]
+    fn rejects_references_to_undefined_variables() {
+        assert_eq!(
+            evaluate("b + 2"),
+            Err("Undefined variable 'b'.".to_string())
+        );
+    }
+
+    #[test]
+    fn block_variables_do_not_leak_out() {
+        let source = r#"var a = "outer"; { var a = "inner"; a = a + "!"; } a"#;
+        assert_eq!(evaluate(source), Ok("outer".to_string()));
+    }
+
+    #[test]
+    fn logical_operators_skip_the_right_operand_when_short_circuiting() {
+        assert_eq!(evaluate("nil and undefined"), Ok("nil".to_string()));
+        assert_eq!(
+            evaluate("nil or undefined"),
+            Err("Undefined variable 'undefined'.".to_string())
+        );
+    }
+
+    #[test]
+    fn logical_operators_return_an_operand() {
+        assert_eq!(evaluate(r#"nil or "x""#), Ok("x".to_string()));
+        assert_eq!(evaluate("nil and 1"), Ok("nil".to_string()));
+        assert_eq!(evaluate("nil or nil and 1"), Ok("nil".to_string()));
+    }
+
+    #[test]
+    fn negates_by_truthiness() {
+        assert_eq!(evaluate("!0"), Ok("false".to_string()));
+        assert_eq!(evaluate(r#"!"""#), Ok("false".to_string()));
+        assert_eq!(evaluate("!nil"), Ok("true".to_string()));
+        assert_eq!(evaluate("!false"), Ok("true".to_string()));
+    }
+
+    #[test]
+    fn truthy_operands_short_circuit_or() {
+        assert_eq!(evaluate("0 or undefined"), Ok("0".to_string()));
+        assert_eq!(evaluate(r#""" and 1"#), Ok("1".to_string()));
+    }
+
+    #[test]
+    fn runs_while_and_for_loops() {
+        let source = "var sum = 0; for (var i = 1; i <= 10; i = i + 1) sum = sum + i; sum";
+        assert_eq!(evaluate(source), Ok("55".to_string()));
+        let source = "var i = 0; while (i < 3) i = i + 1; i";
+        assert_eq!(evaluate(source), Ok("3".to_string()));
+    }
+
+    #[test]
+    fn keeps_definitions_between_inputs() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(
+            evaluate_with(&mut interpreter, "var x = 1; x"),
+            Ok("1".to_string())
+        );
+        assert_eq!(
+            evaluate_with(&mut interpreter, "x = x + 1"),
+            Ok("2".to_string())
+        );
+        assert_eq!(
+            evaluate_with(&mut interpreter, "x * 10"),
+            Ok("20".to_string())
+        );
+    }
+
+    #[test]
+    fn keeps_resolutions_of_separate_inputs_apart() {
+        // Both inputs use `a` at line 1, column 12, once as a local and then as a global
+        let mut interpreter = Interpreter::new();
+        assert_eq!(
+            evaluate_with(&mut interpreter, "{var a=1;{{a;}}} 0"),
+            Ok("0".to_string())
+        );
+        assert_eq!(
+            evaluate_with(&mut interpreter, "var a = 2;  a"),
+            Ok("2".to_string())
+        );
+    }
+
+    #[test]
+    fn evaluates_nested_ternaries() {
+        let source = r#"1 < 2 ? (2 < 3 ? "a" : "b") : "c""#;
+        assert_eq!(evaluate(source), Ok("a".to_string()));
+        assert_eq!(evaluate("false ? 1 : true ? 2 : 3"), Ok("2".to_string()));
+        assert_eq!(evaluate("nil ? 1 : 0 ? 2 : 3"), Ok("2".to_string()));
+    }
+
+    #[test]
+    fn allows_ternary_branches_of_different_types() {
+        assert_eq!(evaluate(r#"true ? 1 : "x""#), Ok("1".to_string()));
+        assert_eq!(evaluate(r#"false ? 1 : "x""#), Ok("x".to_string()));
+    }
+
+    #[test]
+    fn sequence_yields_its_last_operand() {
+        assert_eq!(evaluate("(1, 2, 3)"), Ok("3".to_string()));
+        assert_eq!(evaluate("var a; a = 1, a + 1"), Ok("2".to_string()));
+    }
+
+    #[test]
+    fn sequence_does_not_apply_to_call_arguments() {
+        let source = "fun second(a, b) { return b; } second(1, 2) + second((1, 2), 3)";
+        assert_eq!(evaluate(source), Ok("5".to_string()));
+    }
+
+    #[test]
+    fn rejects_division_and_remainder_by_zero() {
+        assert_eq!(evaluate("4 / 0"), Err("Division by zero.".to_string()));
+        assert_eq!(evaluate("4 % 0"), Err("Division by zero.".to_string()));
+        assert_eq!(evaluate("0 / 4"), Ok("0".to_string()));
+    }
+
+    #[test]
+    fn reports_unbounded_recursion_as_a_stack_overflow() {
+        // Like the lox binary, run on a stack large enough for the default depth limit in
+        // unoptimised builds
+        let errors = on_large_stack(|| errors_of("fun down(n) { return down(n + 1); } down(0);"));
+        assert_eq!(errors, ["Stack overflow."]);
+    }
+
+    #[test]
+    fn limits_calls_to_the_configured_depth() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_call_depth(10);
+        let source = "fun depth(n) { while (n > 0) return 1 + depth(n - 1); return 0; } nil";
+        assert!(evaluate_with(&mut interpreter, source).is_ok());
+
+        assert_eq!(
+            evaluate_with(&mut interpreter, "depth(9)"),
+            Ok("9".to_string())
+        );
+        assert_eq!(
+            evaluate_with(&mut interpreter, "depth(10)"),
+            Err("Stack overflow.".to_string())
+        );
+        // The depth unwinds with the error, so later calls are unaffected
+        assert_eq!(
+            evaluate_with(&mut interpreter, "depth(9)"),
+            Ok("9".to_string())
+        );
+    }
+
+    #[test]
+    fn writes_program_output_to_the_given_sink() {
+        let (mut interpreter, output) = capturing_interpreter();
+        assert!(run_with(&mut interpreter, "print 1; print 2;").is_ok());
+        assert_eq!(output.contents().into_bytes(), b"1\n2\n");
+    }
+
+    #[test]
+    fn prints_the_value_of_a_lone_expression_in_the_repl() {
+        let (mut interpreter, output) = capturing_interpreter();
+        let statements = parse_source("3 * 4;").expect("source should parse");
+        assert!(interpreter.interpret_repl(statements).is_ok());
+        assert_eq!(output.contents(), "12\n");
+    }
+
+    #[test]
+    fn does_not_print_expression_values_outside_the_repl() {
+        let (mut interpreter, output) = capturing_interpreter();
+        let statements = parse_source("3 * 4;").expect("source should parse");
+        let values = interpreter
+            .interpret(statements)
+            .map_err(|_| ())
+            .expect("should run");
+        assert!(values == [Value::Number(12.0)]);
+        assert_eq!(output.contents(), "");
+    }
+
+    #[test]
+    fn builds_and_reads_lists() {
+        let source = r#"var l = [1, "a", nil, [2]]; print l; print l[0]; print l[3][0]; print [];"#;
+        assert_eq!(output_of(source), "[1, a, nil, [2]]\n1\n2\n[]\n");
+    }
+
+    #[test]
+    fn rejects_out_of_range_and_non_integer_indices() {
+        assert_eq!(
+            errors_of("var l = [1]; print l[1];"),
+            ["List index 1 is out of range for a list of length 1."]
+        );
+        assert_eq!(
+            errors_of("print [1][-1];"),
+            ["List index -1 is out of range for a list of length 1."]
+        );
+        assert_eq!(
+            errors_of("print [1][0.5];"),
+            ["List index '0.5' must be an integer."]
+        );
+        assert_eq!(
+            errors_of("print 1[0];"),
+            ["Can only index into lists, not '1'."]
+        );
+    }
+
+    #[test]
+    fn assigns_to_list_elements_in_place() {
+        let source = "var l = [1, 2]; var alias = l; print l[1] = 5; print alias;";
+        assert_eq!(output_of(source), "5\n[1, 5]\n");
+    }
+
+    #[test]
+    fn rejects_assignment_past_the_end_of_a_list() {
+        assert_eq!(
+            errors_of("var l = [1]; l[1] = 2;"),
+            ["List index 1 is out of range for a list of length 1."]
+        );
+    }
+
+    /// Interpret a program which is expected to fail at runtime and return the kind of error
+    fn runtime_error_kind(source: &str) -> RuntimeErrorKind {
+        match interpret(&mut Interpreter::new(), source) {
+            Ok(_) => panic!("Program succeeded but was expected to fail"),
+            Err(error) => error.kind,
+        }
+    }
+
+    #[test]
+    fn tells_runtime_errors_apart_by_kind() {
+        assert_eq!(
+            runtime_error_kind("x;"),
+            RuntimeErrorKind::UndefinedVariable
+        );
+        assert_eq!(runtime_error_kind("-nil;"), RuntimeErrorKind::TypeMismatch);
+        assert_eq!(
+            runtime_error_kind("1 / 0;"),
+            RuntimeErrorKind::DivisionByZero
+        );
+        assert_eq!(
+            runtime_error_kind("fun f(a) {} f();"),
+            RuntimeErrorKind::Arity
+        );
+        assert_eq!(
+            runtime_error_kind(r#""f"();"#),
+            RuntimeErrorKind::NotCallable
+        );
+        assert_eq!(
+            runtime_error_kind("[1][2];"),
+            RuntimeErrorKind::IndexOutOfRange
+        );
+        assert_eq!(
+            runtime_error_kind("class A {} A().b;"),
+            RuntimeErrorKind::UndefinedProperty
+        );
+        assert_eq!(
+            runtime_error_kind("len(1);"),
+            RuntimeErrorKind::NativeFunction
+        );
+    }
+
+    #[test]
+    fn raises_to_powers_right_associatively() {
+        assert_eq!(
+            output_of("print 2 ** 3 ** 2; print (2 ** 3) ** 2; print -2 ** 2; print 2 ** -1;"),
+            "512\n64\n-4\n0.5\n"
+        );
+    }
+
+    #[test]
+    fn rejects_powers_of_non_numbers() {
+        assert_eq!(
+            errors_of(r#"print 2 ** "a";"#),
+            ["Operands '2' and 'a' must both be numbers."]
+        );
+    }
+
+    #[test]
+    fn repeats_strings_by_whole_counts() {
+        assert_eq!(output_of(r#"print "x" * 0;"#), "\n");
+        assert_eq!(output_of(r#"print "x" * 3;"#), "xxx\n");
+        assert_eq!(output_of(r#"print 2 * "ab";"#), "abab\n");
+    }
+
+    #[test]
+    fn rejects_negative_and_fractional_repetition_counts() {
+        assert_eq!(
+            errors_of(r#"print "x" * -1;"#),
+            ["String repetition count '-1' must be a non-negative integer."]
+        );
+        assert_eq!(
+            errors_of(r#"print "x" * 1.5;"#),
+            ["String repetition count '1.5' must be a non-negative integer."]
+        );
+    }
+
+    #[test]
+    fn rejects_repetition_too_long_to_build() {
+        for source in [r#"print "ab" * 1e300;"#, r#"print "ab" * 4e18;"#] {
+            assert_eq!(
+                errors_of(source),
+                ["String repetition would be longer than 1073741824 bytes."]
+            );
+        }
+        assert_eq!(output_of(r#"print "" * 1e300;"#), "\n");
+    }
+
+    #[test]
+    fn concatenates_mixed_operands_when_lenient() {
+        let (mut interpreter, output) = capturing_interpreter();
+        interpreter.set_lenient_concatenation(true);
+        let source = r#"print "count: " + 5; print 5 + " apples"; print "done: " + true;"#;
+        run_with(&mut interpreter, source).unwrap();
+        assert_eq!(output.contents(), "count: 5\n5 apples\ndone: true\n");
+    }
+
+    #[test]
+    fn rejects_mixed_operands_when_strict() {
+        assert_eq!(
+            errors_of(r#"print "count: " + 5;"#),
+            ["Operands 'count: ' and '5' must both be numbers or strings."]
+        );
+        assert_eq!(
+            errors_of(r#"print 5 + " apples";"#),
+            ["Operands '5' and ' apples' must both be numbers or strings."]
+        );
+        assert_eq!(output_of(r#"print "a" + "b"; print 1 + 2;"#), "ab\n3\n");
+    }
+
+    #[test]
+    fn executes_print_statements() {
+        assert_eq!(
+            output_of(r#"print 1; print "two"; print nil;"#),
+            "1\ntwo\nnil\n"
+        );
+    }
+
+    #[test]
+    fn returns_the_values_of_expression_statements() {
+        let values = run_source(r#"1 + 2; "a";"#).map_or(Vec::new(), |output| output.values);
+        assert!(values == [Value::Number(3.0), Value::String("a".into())]);
+    }
+
+    #[test]
+    fn executes_var_statements() {
+        assert_eq!(output_of("var a; var b = 2; print a; print b;"), "nil\n2\n");
+    }
+
+    #[test]
+    fn executes_blocks_in_their_own_scope() {
+        let source = "var a = 1; { var a = 2; var b = 3; print a; print b; } print a;";
+        assert_eq!(output_of(source), "2\n3\n1\n");
+    }
+
+    #[test]
+    fn shares_function_bodies_between_declarations() {
+        // Each run of the declaration creates a new closure over the same body
+        let source = "
+            var fs = [nil, nil];
+            for (var i = 0; i < 2; i = i + 1) {
+                var n = i;
+                fun f() { return n; }
+                fs[i] = f;
             }
-        )
+            print fs[0]();
+            print fs[1]();
+        ";
+        assert_eq!(output_of(source), "0\n1\n");
+    }
+
+    #[test]
+    fn reads_unassigned_variables_as_nil_by_default() {
+        assert_eq!(
+            output_of("var x; print x; { var y; print y; }"),
+            "nil\nnil\n"
+        );
+    }
+
+    #[test]
+    fn rejects_reading_unassigned_variables_without_implicit_nil() {
+        let (mut interpreter, output) = capturing_interpreter();
+        interpreter.set_implicit_nil(false);
+
+        let errors = run_with(&mut interpreter, "var x; print x;")
+            .err()
+            .unwrap_or_default();
+        assert_eq!(errors[0].message, "Variable 'x' used before assignment.");
+        let errors = run_with(&mut interpreter, "{ var y; print y; }")
+            .err()
+            .unwrap_or_default();
+        assert_eq!(errors[0].message, "Variable 'y' used before assignment.");
+
+        let source = "var z; z = nil; print z; { var w; w = 1; print w; }";
+        assert!(run_with(&mut interpreter, source).is_ok());
+        assert_eq!(output.contents(), "nil\n1\n");
+    }
+
+    #[test]
+    fn stops_loops_which_exceed_the_iteration_cap() {
+        let (mut interpreter, _) = capturing_interpreter();
+        interpreter.set_max_loop_iterations(Some(100));
+
+        let errors = run_with(&mut interpreter, "while (true) {}")
+            .err()
+            .unwrap_or_default();
+        assert_eq!(errors[0].message, "Loop exceeded 100 iterations.");
+    }
+
+    #[test]
+    fn counts_iterations_afresh_for_each_loop() {
+        let (mut interpreter, output) = capturing_interpreter();
+        interpreter.set_max_loop_iterations(Some(5));
+        let source = "
+            fun count() { var n = 0; for (var i = 0; i < 5; i = i + 1) n = n + 1; return n; }
+            print count() + count();
+        ";
+        assert!(run_with(&mut interpreter, source).is_ok());
+        assert_eq!(output.contents(), "10\n");
+    }
+
+    #[test]
+    fn compares_numbers_exactly_by_default() {
+        assert_eq!(
+            output_of("print 0.1 + 0.2 == 0.3; print 0.1 + 0.2 != 0.3;"),
+            "false\ntrue\n"
+        );
+    }
+
+    #[test]
+    fn compares_numbers_within_the_equality_epsilon() {
+        let (mut interpreter, output) = capturing_interpreter();
+        interpreter.set_equality_epsilon(Some(1e-9));
+        let source =
+            "print 0.1 + 0.2 == 0.3; print 0.1 + 0.2 != 0.3; print 1 == 1.001; print 1 != 1.001;";
+        assert!(run_with(&mut interpreter, source).is_ok());
+        assert_eq!(output.contents(), "true\nfalse\nfalse\ntrue\n");
+    }
+
+    #[test]
+    fn passes_printed_values_to_the_print_callback() {
+        let printed = Rc::new(RefCell::new(Vec::new()));
+        let (mut interpreter, output) = capturing_interpreter();
+        let collected = Rc::clone(&printed);
+        interpreter.set_on_print(Box::new(move |value| {
+            collected.borrow_mut().push(value.clone())
+        }));
+
+        assert!(run_with(&mut interpreter, r#"print 1, "a"; print nil == nil;"#).is_ok());
+        assert!(
+            *printed.borrow()
+                == [
+                    Value::Number(1.0),
+                    Value::String("a".into()),
+                    Value::Boolean(true)
+                ]
+        );
+        assert_eq!(output.contents(), "1 a\ntrue\n");
+    }
+
+    #[test]
+    fn prints_arguments_separated_by_spaces() {
+        assert_eq!(output_of(r#"print "one";"#), "one\n");
+        assert_eq!(output_of(r#"var b = 2; print "a", b, b * 1.5;"#), "a 2 3\n");
     }
 }
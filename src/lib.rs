@@ -0,0 +1,111 @@
+mod builtins;
+#[cfg(feature = "bytecode")]
+pub mod bytecode;
+pub mod callable;
+pub mod class;
+pub mod constant_folder;
+pub mod environment;
+pub mod error;
+pub mod expression;
+pub mod interpreter;
+pub mod parser;
+pub mod resolver;
+pub mod scanner;
+pub mod statement;
+pub mod symbol;
+#[cfg(test)]
+mod test_util;
+pub mod token;
+mod util;
+pub mod value;
+
+pub use error::{Diagnostic, Severity};
+pub use expression::Expression;
+pub use interpreter::Interpreter;
+pub use statement::Statement;
+pub use token::Token;
+pub use util::{AstPrinter, ToJson, ToSource};
+pub use value::Value;
+
+use parser::Parser;
+use resolver::Resolver;
+use scanner::Scanner;
+
+/// Scan and parse a Lox program without running it, returning its top-level statements or every
+/// error that was found
+pub fn parse_source(source: &str) -> Result<Vec<Statement>, Vec<Diagnostic>> {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens()?;
+
+    let mut parser = Parser::new(tokens);
+    parser.parse()
+}
+
+/// What a program that ran to completion produced
+pub struct RunOutput {
+    /// Value of each top-level expression statement
+    pub values: Vec<Value>,
+    /// Diagnostics of [`Severity::Warning`], which did not stop the program from running
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// Scan, parse and interpret a Lox program with a fresh interpreter, returning the value of each
+/// top-level expression statement along with any warnings, or every error that was found. Any
+/// warnings are included with the errors too.
+pub fn run_source(source: &str) -> Result<RunOutput, Vec<Diagnostic>> {
+    let statements = parse_source(source)?;
+
+    let mut interpreter = Interpreter::new();
+    let mut resolver = Resolver::new(&mut interpreter);
+    let resolved = resolver.resolve(&statements);
+    let mut warnings = resolver.warnings().to_vec();
+    if let Err(errors) = resolved {
+        warnings.extend(errors);
+        return Err(warnings);
+    }
+
+    match interpreter.interpret(statements) {
+        Ok(values) => Ok(RunOutput { values, warnings }),
+        Err(error) => {
+            warnings.push(Diagnostic::from(error));
+            Err(warnings)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_source, run_source, Severity};
+    use crate::Value;
+
+    #[test]
+    fn parses_without_running() {
+        let statements =
+            parse_source("var a = 1;\nfun f() { print a; }\n{ f(); f(); }\nundefined;");
+        assert_eq!(statements.map(|statements| statements.len()).ok(), Some(4));
+
+        let errors = parse_source("var = 1;").err().unwrap_or_default();
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn returns_warnings_with_the_values() {
+        let output = run_source("fun f() { return 1; print 2; } f();");
+        let Ok(output) = output else {
+            panic!("Program failed");
+        };
+        assert!(output.values == [Value::Number(1.0)]);
+        assert_eq!(output.warnings.len(), 1);
+        assert_eq!(output.warnings[0].severity, Severity::Warning);
+        assert_eq!(output.warnings[0].message, "Unreachable code after return.");
+    }
+
+    #[test]
+    fn returns_warnings_with_the_errors() {
+        let Err(diagnostics) = run_source("fun f() { return; f(); } print x;") else {
+            panic!("Program succeeded but was expected to fail");
+        };
+        let severities: Vec<_> = diagnostics.iter().map(|d| d.severity).collect();
+        assert_eq!(severities, [Severity::Warning, Severity::Error]);
+    }
+}
@@ -1,70 +1,184 @@
-mod error;
-mod expression;
-mod interpreter;
-mod parser;
-mod scanner;
-mod token;
-mod util;
-
-use error::{get_error_flag, set_error_flag};
-use parser::Parser;
-use scanner::Scanner;
-use token::Token;
-
-use interpreter::Interpreter;
-use std::io::{self, BufRead, Write};
-use std::{env, fs, process};
+#[cfg(feature = "bytecode")]
+use lox::bytecode::{Compiler, Vm};
+use lox::constant_folder::ConstantFolder;
+use lox::error::{Diagnostic, DiagnosticKind, ErrorReporter, ReportFormat};
+use lox::parser::Parser;
+use lox::resolver::Resolver;
+use lox::scanner::Scanner;
+use lox::token::{Token, TokenType};
+use lox::{Interpreter, ToSource};
+
+use std::ffi::OsString;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::{env, fs, process, thread};
+
+#[cfg(test)]
+#[path = "test_util/output.rs"]
+mod test_util;
+
+/// The interpreter recurses on the Rust stack for every Lox call, so it runs on a thread with a
+/// stack large enough to reach the interpreter's own call depth limit in unoptimised builds
+const INTERPRETER_STACK_SIZE: usize = 256 * 1024 * 1024;
 
 fn main() {
-    env::set_var("RUST_BACKTRACE", "1");
-    let args = env::args().collect::<Vec<String>>();
-    let interpreter = Interpreter::new();
-    match args.len() {
-        // Running the program standalone - open REPL
-        1 => {
-            if let Err(e) = run_repl(interpreter) {
-                eprintln!("Error while running REPL: {e}");
-                process::exit(74);
+    let interpreter_thread = thread::Builder::new()
+        .stack_size(INTERPRETER_STACK_SIZE)
+        .spawn(run_main)
+        .expect("Failed to spawn interpreter thread");
+
+    if interpreter_thread.join().is_err() {
+        process::exit(70);
+    }
+}
+
+/// Command line options controlling what is run and how
+#[derive(Default)]
+struct Options {
+    /// Path of the script to run, where `-` stands for standard input. Without one, the REPL
+    /// is opened.
+    script: Option<String>,
+    /// Print the scanned tokens instead of running the program
+    dump_tokens: bool,
+    /// Print the parsed syntax tree instead of running the program
+    dump_ast: bool,
+    /// Print the program reformatted in the canonical layout instead of running it
+    format_source: bool,
+    /// How errors are reported, which is JSON if asked for and otherwise depends on where
+    /// they are written
+    report_format: ReportFormat,
+    /// Replace operators applied to literals with their results before running the program
+    fold_constants: bool,
+    /// Print every global variable after running the script
+    dump_env: bool,
+    /// Compile the script to bytecode and run it on the virtual machine, rather than walking the
+    /// syntax tree
+    #[cfg(feature = "bytecode")]
+    bytecode: bool,
+}
+
+impl Options {
+    /// Parse the arguments following the program name, or `None` if they are not valid
+    fn parse(args: impl Iterator<Item = String>) -> Option<Self> {
+        let mut options = Options::default();
+
+        for arg in args {
+            match arg.as_str() {
+                "--tokens" => options.dump_tokens = true,
+                "--ast" => options.dump_ast = true,
+                "--fmt" => options.format_source = true,
+                "--json-errors" => options.report_format = ReportFormat::Json,
+                "--dump-env" => options.dump_env = true,
+                "--fold" => options.fold_constants = true,
+                #[cfg(feature = "bytecode")]
+                "--bytecode" => options.bytecode = true,
+                // A lone `-` is a script path, anything else starting with `-` is an unknown flag
+                flag if flag.starts_with('-') && flag != "-" => return None,
+                _ if options.script.is_some() => return None,
+                _ => options.script = Some(arg),
             }
         }
+
+        // Globals are only dumped once the whole script has run, which never happens in the REPL
+        if options.dump_env && options.is_repl() {
+            return None;
+        }
+
+        // The virtual machine starts afresh for each run, so it can't keep REPL state
+        #[cfg(feature = "bytecode")]
+        if options.bytecode && options.is_repl() {
+            return None;
+        }
+
+        Some(options)
+    }
+
+    fn is_repl(&self) -> bool {
+        self.script.is_none()
+    }
+}
+
+fn run_main() {
+    env::set_var("RUST_BACKTRACE", "1");
+    let Some(mut options) = Options::parse(env::args().skip(1)) else {
+        let bytecode = if cfg!(feature = "bytecode") {
+            " [--bytecode]"
+        } else {
+            ""
+        };
+        println!("Usage: jlox [--tokens] [--ast] [--fmt] [--json-errors] [--dump-env] [--fold]{bytecode} [script | -]");
+        process::exit(64)
+    };
+    if options.report_format != ReportFormat::Json {
+        options.report_format = report_format(env::var_os("NO_COLOR"), io::stderr().is_terminal());
+    }
+
+    let mut interpreter = Interpreter::new();
+    match &options.script {
         // Providing a file - run given file
-        2 => {
-            if let Err(e) = run_file(
-                interpreter,
-                args.get(2).expect("Failed to get source code file name"),
-            ) {
+        Some(path) => {
+            if let Err(e) = run_file(&mut interpreter, path, &options) {
                 eprintln!("Error: {e}");
                 process::exit(74);
             }
         }
-        // Something else, correct the user
-        _ => {
-            println!("Usage: jlox [script]");
-            process::exit(64)
+        // Running the program standalone - open REPL
+        None => {
+            if let Err(e) = run_repl(&mut interpreter, &options) {
+                eprintln!("Error while running REPL: {e}");
+                process::exit(74);
+            }
         }
     };
 }
 
-fn run_file(interpreter: Interpreter, path: &str) -> io::Result<()> {
-    let bytes = fs::read(path)?;
-    let content = String::from_utf8_lossy(&bytes).to_string();
-    run(&interpreter, content);
-
-    if error::get_error_flag() {
-        process::exit(65)
-    }
-    if error::get_runtime_error_flag() {
-        process::exit(70)
+fn run_file(interpreter: &mut Interpreter, path: &str, options: &Options) -> io::Result<()> {
+    let content = read_script(path, io::stdin())?;
+    if let Err(diagnostics) = run(interpreter, &content, options, &mut io::stdout()) {
+        report(&content, &diagnostics, options);
+        process::exit(exit_code(&diagnostics))
     }
     Ok(())
 }
 
-fn run_repl(interpreter: Interpreter) -> io::Result<()> {
+/// Exit status for a script which failed with the given errors
+fn exit_code(diagnostics: &[Diagnostic]) -> i32 {
+    // As in jlox, errors in the text of the program take precedence over errors running it,
+    // exiting with 65 rather than 70
+    let is_runtime_error = diagnostics
+        .iter()
+        .all(|diagnostic| diagnostic.kind == DiagnosticKind::Runtime);
+    if is_runtime_error {
+        70
+    } else {
+        65
+    }
+}
+
+/// Read the script at `path`, or from `stdin` if the path is `-`
+fn read_script(path: &str, mut stdin: impl Read) -> io::Result<String> {
+    let bytes = if path == "-" {
+        let mut bytes = Vec::new();
+        stdin.read_to_end(&mut bytes)?;
+        bytes
+    } else {
+        fs::read(path)?
+    };
+    Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+fn run_repl(interpreter: &mut Interpreter, options: &Options) -> io::Result<()> {
     let stdin = io::stdin();
     let mut reader = stdin.lock();
+    let mut history = History::load(history_path(
+        env::var_os("LOX_HISTORY"),
+        env::var_os("HOME"),
+    ));
+    // Lines entered so far for a statement which spans several of them
+    let mut buffer = String::new();
 
     loop {
-        print!("> ");
+        print!("{}", if buffer.is_empty() { "> " } else { "... " });
         // Flush to ensure prompt is displayed immediately
         io::stdout().flush()?;
 
@@ -75,25 +189,540 @@ fn run_repl(interpreter: Interpreter) -> io::Result<()> {
         if bytes_read == 0 {
             break;
         }
+        // History is only a convenience, so failing to record it doesn't end the session
+        let _ = history.push(&line);
 
-        let trimmed_line = line.trim().to_string();
-        run(&interpreter, trimmed_line);
-        set_error_flag(false);
+        // Meta-commands are only recognised at the start of a statement
+        if buffer.is_empty() && line.trim_start().starts_with(':') {
+            match run_command(interpreter, line.trim(), options, &mut io::stdout()) {
+                Command::Continue => continue,
+                Command::Quit => break,
+            }
+        }
+
+        // Keep reading until the input is complete, though a blank line runs it regardless so
+        // that its errors are shown
+        buffer.push_str(&line);
+        if !line.trim().is_empty() && is_incomplete(&buffer) {
+            continue;
+        }
+
+        let source = std::mem::take(&mut buffer);
+        let trimmed_source = source.trim();
+        if let Err(diagnostics) = run(interpreter, trimmed_source, options, &mut io::stdout()) {
+            report(trimmed_source, &diagnostics, options);
+        }
     }
 
     Ok(())
 }
 
-fn run(interpreter: &Interpreter, source: String) {
-    let mut scanner = Scanner::new(source);
-    let tokens: Vec<Token> = scanner.scan_tokens();
+/// What the REPL should do after a meta-command
+enum Command {
+    Continue,
+    Quit,
+}
+
+/// Run a REPL meta-command, which is a line starting with `:` such as `:help`, writing what it
+/// shows to `out`
+fn run_command(
+    interpreter: &mut Interpreter,
+    line: &str,
+    options: &Options,
+    out: &mut dyn Write,
+) -> Command {
+    let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+    let argument = argument.trim();
+
+    match command {
+        ":help" => write!(
+            out,
+            concat!(
+                ":help           Show this list of commands\n",
+                ":quit           Leave the REPL\n",
+                ":tokens <code>  Print the tokens scanned from the code\n",
+                ":ast <code>     Print the syntax tree parsed from the code\n",
+                ":reset          Forget every variable, function and class defined so far\n",
+            )
+        )
+        .expect("Failed to write help"),
+        ":quit" => return Command::Quit,
+        ":tokens" | ":ast" => {
+            let dump_options = Options {
+                dump_tokens: command == ":tokens",
+                dump_ast: command == ":ast",
+                report_format: options.report_format,
+                ..Options::default()
+            };
+            if let Err(diagnostics) = run(interpreter, argument, &dump_options, out) {
+                report(argument, &diagnostics, options);
+            }
+        }
+        ":reset" => *interpreter = Interpreter::new(),
+        _ => eprintln!("Unknown command '{command}'. Type :help for a list of commands."),
+    }
+
+    Command::Continue
+}
+
+/// Lines entered into the REPL, kept across sessions in a file. Each line is added to the file
+/// as soon as it is entered, so the history survives the REPL being killed.
+struct History {
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// Most lines kept in the history file, dropping the oldest first
+    const MAX_LINES: usize = 1000;
+
+    /// Open the history kept in the file at `path`, or keep no history if there is none
+    fn load(path: Option<PathBuf>) -> Self {
+        // A missing or unreadable file just means starting afresh, and one which can't be
+        // trimmed is merely longer than it needs to be
+        if let Some(path) = &path {
+            let content = fs::read_to_string(path).unwrap_or_default();
+            let lines: Vec<&str> = content.lines().collect();
+            if lines.len() > Self::MAX_LINES {
+                let kept: String = lines[lines.len() - Self::MAX_LINES..]
+                    .iter()
+                    .map(|line| format!("{line}\n"))
+                    .collect();
+                let _ = fs::write(path, kept);
+            }
+        }
+
+        History { path }
+    }
+
+    fn push(&mut self, line: &str) -> io::Result<()> {
+        let line = line.trim();
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        writeln!(file, "{line}")
+    }
+}
+
+/// File the REPL history is kept in, given the `LOX_HISTORY` and `HOME` environment variables.
+/// `LOX_HISTORY` names a file other than `~/.lox_history`, or turns history off if it is empty.
+fn history_path(lox_history: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    match lox_history {
+        Some(path) if path.is_empty() => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => home.map(|home| Path::new(&home).join(".lox_history")),
+    }
+}
+
+/// Whether REPL input stops part way through a statement, so more lines should be read before
+/// running it
+fn is_incomplete(source: &str) -> bool {
+    let tokens = match Scanner::new(source.to_string()).scan_tokens() {
+        Ok(tokens) => tokens,
+        // A string or block comment left open may be closed on a later line
+        Err(diagnostics) => {
+            return diagnostics
+                .iter()
+                .any(|diagnostic| diagnostic.message.starts_with("Unterminated"))
+        }
+    };
+
+    let depth = tokens
+        .iter()
+        .fold(0, |depth: i32, token| match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth + 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => depth - 1,
+            _ => depth,
+        });
+    if depth > 0 {
+        return true;
+    }
+
+    // Errors found only on reaching the end of the input are for a statement not yet finished
+    let end = tokens.last().map(|token| (token.line, Some(token.column)));
+    match Parser::new(tokens).parse_repl() {
+        Ok(_) => false,
+        Err(diagnostics) => diagnostics
+            .iter()
+            .all(|diagnostic| Some((diagnostic.line, diagnostic.column)) == end),
+    }
+}
+
+/// Run a program, or dump the stages of running it that the options ask for to `out`
+fn run(
+    interpreter: &mut Interpreter,
+    source: &str,
+    options: &Options,
+    out: &mut dyn Write,
+) -> Result<(), Vec<Diagnostic>> {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens: Vec<Token> = scanner.scan_tokens()?;
+
+    if options.dump_tokens {
+        for token in &tokens {
+            writeln!(out, "{}", token).expect("Failed to write tokens");
+        }
+    }
+
+    // Dumping tokens alone doesn't need the program to be parsed, but dumping the tree does
+    if options.dump_tokens && !options.dump_ast && !options.format_source {
+        return Ok(());
+    }
 
     let mut parser = Parser::new(tokens);
-    let expression = parser.parse();
+    let mut statements = if options.is_repl() {
+        parser.parse_repl()?
+    } else {
+        parser.parse()?
+    };
+
+    // Folding comes before dumping, so that the folded tree can be inspected
+    if options.fold_constants {
+        statements = ConstantFolder::new(interpreter).fold(statements);
+    }
+
+    if options.dump_ast {
+        for statement in &statements {
+            writeln!(out, "{}", statement).expect("Failed to write syntax tree");
+        }
+        return Ok(());
+    }
+
+    if options.format_source {
+        write!(out, "{}", statements.to_source()).expect("Failed to write source");
+        return Ok(());
+    }
+
+    let mut resolver = Resolver::new(interpreter);
+    let resolved = resolver.resolve(&statements);
+    report(source, resolver.warnings(), options);
+    resolved?;
+
+    #[cfg(feature = "bytecode")]
+    if options.bytecode {
+        let chunk = Compiler::new().compile(&statements)?;
+        return Vm::new()
+            .run(&chunk)
+            .map_err(|error| vec![Diagnostic::from(error)]);
+    }
+
+    let result = if options.is_repl() {
+        interpreter.interpret_repl(statements)
+    } else {
+        interpreter.interpret(statements).map(|_| ())
+    };
+
+    // Globals are dumped even if the script failed, to help see how far it got
+    if options.dump_env {
+        dump_env(interpreter, out);
+    }
+    result.map_err(|error| vec![Diagnostic::from(error)])
+}
+
+fn dump_env(interpreter: &Interpreter, out: &mut dyn Write) {
+    for (name, value) in interpreter.globals() {
+        match value {
+            Some(value) => writeln!(out, "{name} = {value}"),
+            None => writeln!(out, "{name} (unassigned)"),
+        }
+        .expect("Failed to write globals");
+    }
+}
+
+/// Format for reporting errors as text, given the value of the `NO_COLOR` environment variable
+/// and whether standard error is a terminal. Errors are only coloured for a person reading a
+/// terminal, respecting https://no-color.org
+fn report_format(no_color: Option<OsString>, is_tty: bool) -> ReportFormat {
+    if is_tty && no_color.is_none_or(|value| value.is_empty()) {
+        ReportFormat::Coloured
+    } else {
+        ReportFormat::Plain
+    }
+}
+
+fn report(source: &str, diagnostics: &[Diagnostic], options: &Options) {
+    let reporter = ErrorReporter::new(source, options.report_format);
+    for diagnostic in diagnostics {
+        eprintln!("{}", reporter.format(diagnostic));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::{env, fs, io, process};
+
+    use super::{
+        exit_code, history_path, is_incomplete, read_script, report_format, run, run_command,
+        Command, Diagnostic, History, Interpreter, Options, ReportFormat,
+    };
+    use crate::test_util::capturing_interpreter;
+
+    fn options(args: &[&str]) -> Options {
+        Options::parse(args.iter().map(|arg| arg.to_string())).expect("Invalid arguments")
+    }
+
+    #[test]
+    fn takes_the_script_from_the_first_argument() {
+        assert_eq!(
+            options(&["script.lox"]).script.as_deref(),
+            Some("script.lox")
+        );
+        assert!(options(&[]).is_repl());
+        assert!(Options::parse(["a.lox", "b.lox"].map(String::from).into_iter()).is_none());
+    }
+
+    #[test]
+    fn colours_errors_only_on_a_terminal_without_no_color() {
+        assert_eq!(report_format(None, true), ReportFormat::Coloured);
+        assert_eq!(report_format(Some("".into()), true), ReportFormat::Coloured);
+        assert_eq!(report_format(Some("1".into()), true), ReportFormat::Plain);
+        assert_eq!(report_format(None, false), ReportFormat::Plain);
+        assert_eq!(report_format(Some("1".into()), false), ReportFormat::Plain);
+    }
 
-    if get_error_flag() {
-        return;
+    #[test]
+    fn reports_json_errors_when_asked() {
+        assert_eq!(
+            options(&["--json-errors"]).report_format,
+            ReportFormat::Json
+        );
+        assert_eq!(options(&[]).report_format, ReportFormat::Plain);
     }
 
-    interpreter.interpret(expression.expect("Something went wrong"));
+    #[test]
+    fn runs_a_file_end_to_end() {
+        let path = env::temp_dir().join(format!("lox-end-to-end-{}.lox", process::id()));
+        fs::write(&path, "var a = 1;\na = a + 2;\n").unwrap();
+
+        let options = options(&[path.to_str().unwrap()]);
+        let source = read_script(options.script.as_deref().unwrap(), io::empty());
+        fs::remove_file(&path).unwrap();
+
+        let mut interpreter = Interpreter::new();
+        assert!(run(
+            &mut interpreter,
+            &source.unwrap(),
+            &options,
+            &mut io::sink()
+        )
+        .is_ok());
+        assert!(read_script(options.script.as_deref().unwrap(), io::empty()).is_err());
+    }
+
+    #[test]
+    fn exits_as_a_runtime_error_on_division_by_zero() {
+        let diagnostics = run(
+            &mut Interpreter::new(),
+            "print 4 / 0;",
+            &options(&["script.lox"]),
+            &mut io::sink(),
+        )
+        .expect_err("Division by zero succeeded");
+        assert_eq!(diagnostics[0].message, "Division by zero.");
+        assert_eq!(exit_code(&diagnostics), 70);
+    }
+
+    #[test]
+    fn runs_a_script_read_from_stdin() {
+        let options = options(&["-"]);
+        assert!(!options.is_repl());
+
+        let stdin = "var a = 1;\na = a + 1;\n".as_bytes();
+        let source = read_script(options.script.as_deref().unwrap(), stdin).unwrap();
+        assert_eq!(source, "var a = 1;\na = a + 1;\n");
+        assert!(run(&mut Interpreter::new(), &source, &options, &mut io::sink()).is_ok());
+    }
+
+    #[test]
+    fn dumps_tokens_without_running() {
+        let mut dump = Vec::new();
+        let options = options(&["--tokens", "-"]);
+        assert!(run(&mut Interpreter::new(), "1 + 2", &options, &mut dump).is_ok());
+
+        assert_eq!(
+            String::from_utf8(dump).unwrap(),
+            "Number 1 Number(1.0)\nPlus + None\nNumber 2 Number(2.0)\nEof  None\n"
+        );
+    }
+
+    #[test]
+    fn dumps_the_syntax_tree_without_running() {
+        let mut interpreter = Interpreter::new();
+        let mut dump = Vec::new();
+        let options = options(&["--ast", "-"]);
+        assert!(run(&mut interpreter, "print -1 * (2 + 3);", &options, &mut dump).is_ok());
+
+        assert_eq!(
+            String::from_utf8(dump).unwrap(),
+            "(print (* (- 1) (group (+ 2 3))))\n"
+        );
+
+        let diagnostics = run(&mut interpreter, "-1 * ;", &options, &mut io::sink())
+            .expect_err("Dumped the tree of an invalid program");
+        assert_eq!(exit_code(&diagnostics), 65);
+    }
+
+    #[test]
+    fn reads_more_lines_until_a_multi_line_while_is_complete() {
+        let lines = [
+            "var x = 1;\n",
+            "while (x > 0)\n",
+            "{\n",
+            "  print \"yes\";\n",
+            "  x = 0;\n",
+            "}\n",
+        ];
+        let mut buffer = String::new();
+        let incomplete = lines
+            .iter()
+            .map(|line| {
+                buffer.push_str(line);
+                is_incomplete(&buffer)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(incomplete, [false, true, true, true, true, false]);
+
+        let (mut interpreter, output) = capturing_interpreter();
+        assert!(run(&mut interpreter, &buffer, &options(&[]), &mut io::sink()).is_ok());
+        assert_eq!(output.contents(), "yes\n");
+
+        assert!(is_incomplete("print \"open"));
+        assert!(!is_incomplete("print 1 +;"));
+    }
+
+    #[test]
+    fn appends_each_line_to_the_history_file_as_it_is_entered() {
+        let path = env::temp_dir().join(format!("lox-history-{}", process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut history = History::load(Some(path.clone()));
+        history.push("var a = 1;\n").unwrap();
+        history.push("   \n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "var a = 1;\n");
+
+        let mut history = History::load(Some(path.clone()));
+        history.push("print a;\n").unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(content, "var a = 1;\nprint a;\n");
+    }
+
+    #[test]
+    fn keeps_only_the_latest_lines_of_history() {
+        let path = env::temp_dir().join(format!("lox-history-long-{}", process::id()));
+        let lines: String = (0..History::MAX_LINES + 5)
+            .map(|i| format!("{i};\n"))
+            .collect();
+        fs::write(&path, lines).unwrap();
+
+        History::load(Some(path.clone()));
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(content.lines().count(), History::MAX_LINES);
+        assert_eq!(content.lines().next(), Some("5;"));
+    }
+
+    #[test]
+    fn finds_the_history_file_from_the_environment() {
+        assert_eq!(
+            history_path(None, Some("/home/ada".into())),
+            Some(PathBuf::from("/home/ada/.lox_history"))
+        );
+        assert_eq!(
+            history_path(Some("/tmp/h".into()), Some("/home/ada".into())),
+            Some(PathBuf::from("/tmp/h"))
+        );
+        assert_eq!(
+            history_path(Some("".into()), Some("/home/ada".into())),
+            None
+        );
+        assert_eq!(history_path(None, None), None);
+    }
+
+    #[test]
+    fn exits_with_jlox_status_for_each_kind_of_error() {
+        let exit_code_of = |source: &str| {
+            let (mut interpreter, _) = capturing_interpreter();
+            let options = options(&["script.lox"]);
+            let diagnostics = run(&mut interpreter, source, &options, &mut io::sink())
+                .expect_err("Program succeeded but was expected to fail");
+            exit_code(&diagnostics)
+        };
+
+        assert_eq!(exit_code_of("print @;"), 65);
+        assert_eq!(exit_code_of("print 1 +;"), 65);
+        assert_eq!(exit_code_of("return 1;"), 65);
+        assert_eq!(exit_code_of("print -nil;"), 70);
+
+        // Errors in the text take precedence over runtime errors reported alongside them
+        let mut diagnostics = run(
+            &mut Interpreter::new(),
+            "print -nil;",
+            &options(&[]),
+            &mut io::sink(),
+        )
+        .expect_err("Program succeeded but was expected to fail");
+        diagnostics.push(Diagnostic::scan(1, 1, "Unexpected character."));
+        assert_eq!(exit_code(&diagnostics), 65);
+    }
+
+    #[test]
+    fn dumps_globals_sorted_by_name_after_running() {
+        let (mut interpreter, output) = capturing_interpreter();
+        interpreter.set_implicit_nil(false);
+        let source = r#"var zebra = "z"; var apple = 1 + 2; var unset; fun f() {} print "ran";"#;
+        let mut dump = Vec::new();
+        assert!(run(
+            &mut interpreter,
+            source,
+            &options(&["--dump-env", "-"]),
+            &mut dump
+        )
+        .is_ok());
+
+        assert_eq!(output.contents(), "ran\n");
+        assert_eq!(
+            String::from_utf8(dump).unwrap(),
+            concat!(
+                "apple = 3\n",
+                "ceil = <fn ceil>\n",
+                "clock = <fn clock>\n",
+                "f = <fn f>\n",
+                "floor = <fn floor>\n",
+                "len = <fn len>\n",
+                "round = <fn round>\n",
+                "substr = <fn substr>\n",
+                "type = <fn type>\n",
+                "unset (unassigned)\n",
+                "zebra = z\n"
+            )
+        );
+    }
+
+    #[test]
+    fn runs_repl_meta_commands() {
+        let (mut interpreter, output) = capturing_interpreter();
+        let repl = options(&[]);
+        let mut shown = Vec::new();
+
+        assert!(matches!(
+            run_command(&mut interpreter, ":ast 1+2", &repl, &mut shown),
+            Command::Continue
+        ));
+        assert_eq!(String::from_utf8(shown).unwrap(), "(; (+ 1 2))\n");
+        assert_eq!(output.contents(), "");
+
+        assert!(matches!(
+            run_command(&mut interpreter, ":quit", &repl, &mut io::sink()),
+            Command::Quit
+        ));
+    }
 }
@@ -1,32 +1,514 @@
-use crate::error::{parse_error, ParseError, ParseResult};
+use std::rc::Rc;
+
+use crate::error::{Diagnostic, ParseError, ParseResult};
 use crate::expression::Expression;
+use crate::statement::{FunctionDeclaration, Statement};
 use crate::token::{Literal, Token, TokenType};
-use crate::util::GenericScanner;
+use crate::util::{GenericScanner, ToSource};
+
+/// The most arguments a call may pass
+const MAX_ARGUMENTS: usize = 255;
+
+/// How deeply statements and expressions may nest. Each level takes tens of kilobytes of stack
+/// to parse in unoptimised builds, so this keeps deeply nested input to an error rather than a
+/// stack overflow, even on a thread with a small stack.
+const MAX_NESTING: usize = 32;
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    /// Whether the final expression statement may omit its semicolon, as in the REPL
+    allow_bare_expression: bool,
+    diagnostics: Vec<Diagnostic>,
+    /// Number of nested statements and expressions being parsed, see [`MAX_NESTING`]
+    depth: usize,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
+    pub fn new(mut tokens: Vec<Token>) -> Self {
+        // Parsing stops at the end of input token, so make sure there is one to stop at rather
+        // than running off the end of tokens that weren't made by the scanner
+        if tokens
+            .last()
+            .is_none_or(|token| token.token_type != TokenType::Eof)
+        {
+            let (line, column, offset) = tokens.last().map_or((1, 1, 0), |token| {
+                let span = token.span();
+                (token.line, token.column + (span.end - span.start), span.end)
+            });
+            tokens.push(Token::new(
+                TokenType::Eof,
+                "".to_string(),
+                Literal::None,
+                line,
+                column,
+                offset,
+            ));
+        }
+
+        Parser {
+            tokens,
+            current: 0,
+            allow_bare_expression: false,
+            diagnostics: Vec::new(),
+            depth: 0,
+        }
     }
 
-    pub fn parse(&mut self) -> Option<Expression> {
-        match self.parse_expression() {
-            Ok(expression) => Some(expression),
+    /// Parse a line of REPL input, where a trailing expression may omit its semicolon
+    pub fn parse_repl(&mut self) -> Result<Vec<Statement>, Vec<Diagnostic>> {
+        self.allow_bare_expression = true;
+        self.parse()
+    }
+
+    pub fn parse(&mut self) -> Result<Vec<Statement>, Vec<Diagnostic>> {
+        let mut statements = Vec::new();
+
+        while !self.is_at_end() {
+            match self.parse_declaration() {
+                Ok(statement) => statements.push(statement),
 
-            Err(e) => {
-                parse_error(self.peek(), e.message);
-                self.synchronise();
-                None
+                // Record the error and carry on from the next statement so that every syntax
+                // error in the input is reported in one pass
+                Err(e) => {
+                    self.error(&self.peek(), e.message);
+                    self.synchronise();
+                }
             }
         }
+
+        if self.diagnostics.is_empty() {
+            Ok(statements)
+        } else {
+            Err(std::mem::take(&mut self.diagnostics))
+        }
+    }
+
+    fn parse_declaration(&mut self) -> ParseResult<Statement> {
+        if self.check_and_consume(&[TokenType::Class]) {
+            self.parse_class_declaration()
+        } else if !self.check_next(TokenType::LeftParen)
+            && self.check_and_consume(&[TokenType::Fun])
+        {
+            // Without a name, `fun (...)` starts an anonymous function expression instead
+            Ok(Statement::Function(Rc::new(
+                self.parse_function("function")?,
+            )))
+        } else if self.check_and_consume(&[TokenType::Var]) {
+            self.parse_var_declaration()
+        } else {
+            self.parse_statement()
+        }
+    }
+
+    fn parse_class_declaration(&mut self) -> ParseResult<Statement> {
+        let name = self.expect_name("class", "Expected class name.")?;
+
+        let superclass = if self.check_and_consume(&[TokenType::Less]) {
+            Some(Expression::Variable {
+                name: self.expect(TokenType::Identifier, "Expected superclass name.")?,
+            })
+        } else {
+            None
+        };
+
+        self.expect(TokenType::LeftBrace, "Expected '{' before class body.")?;
+
+        let mut methods = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            methods.push(Rc::new(self.parse_function("method")?));
+        }
+
+        self.expect(TokenType::RightBrace, "Expected '}' after class body.")?;
+        Ok(Statement::Class {
+            name,
+            superclass,
+            methods,
+        })
+    }
+
+    /// Parse the name, parameters and body of a function, where `kind` describes what is being
+    /// declared for use in error messages
+    fn parse_function(&mut self, kind: &str) -> ParseResult<FunctionDeclaration> {
+        let name = self.expect_name(kind, &format!("Expected {kind} name."))?;
+        self.expect(
+            TokenType::LeftParen,
+            &format!("Expected '(' after {kind} name."),
+        )?;
+        self.finish_function(Some(name), kind)
+    }
+
+    /// Parse the parameters and body of a function, following the opening parenthesis
+    fn finish_function(
+        &mut self,
+        name: Option<Token>,
+        kind: &str,
+    ) -> ParseResult<FunctionDeclaration> {
+        let mut params = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    self.error(
+                        &self.peek(),
+                        format!("Can't have more than {MAX_ARGUMENTS} parameters."),
+                    );
+                }
+                params.push(self.expect_name("parameter", "Expected parameter name.")?);
+                if !self.check_and_consume(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.expect(TokenType::RightParen, "Expected ')' after parameters.")?;
+
+        self.expect(
+            TokenType::LeftBrace,
+            &format!("Expected '{{' before {kind} body."),
+        )?;
+        let body = self.parse_block()?;
+
+        Ok(FunctionDeclaration { name, params, body })
+    }
+
+    fn parse_var_declaration(&mut self) -> ParseResult<Statement> {
+        let name = self.expect_name("variable", "Expected variable name.")?;
+
+        let initializer = if self.check_and_consume(&[TokenType::Equal]) {
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+
+        self.expect(
+            TokenType::Semicolon,
+            "Expected ';' after variable declaration.",
+        )?;
+        Ok(Statement::Var { name, initializer })
+    }
+
+    fn parse_statement(&mut self) -> ParseResult<Statement> {
+        if self.check_and_consume(&[TokenType::For]) {
+            self.parse_for_statement()
+        } else if self.check_and_consume(&[TokenType::If]) {
+            self.parse_if_statement()
+        } else if self.check_and_consume(&[TokenType::Print]) {
+            self.parse_print_statement()
+        } else if self.check_and_consume(&[TokenType::Return]) {
+            self.parse_return_statement()
+        } else if self.check_and_consume(&[TokenType::While]) {
+            self.parse_while_statement()
+        } else if self.check_and_consume(&[TokenType::LeftBrace]) {
+            Ok(Statement::Block(self.parse_block()?))
+        } else {
+            self.parse_expression_statement()
+        }
+    }
+
+    fn parse_block(&mut self) -> ParseResult<Vec<Statement>> {
+        let mut statements = Vec::new();
+
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            statements.push(self.nested(Self::parse_declaration)?);
+        }
+
+        self.expect(TokenType::RightBrace, "Expected '}' after block.")?;
+        Ok(statements)
+    }
+
+    /// Parse a `for` loop, desugaring it into a `while` loop wrapped in blocks for the
+    /// initialiser and increment
+    fn parse_for_statement(&mut self) -> ParseResult<Statement> {
+        let keyword = self.peek_previous();
+        self.expect(TokenType::LeftParen, "Expected '(' after 'for'.")?;
+
+        let initializer = if self.check_and_consume(&[TokenType::Semicolon]) {
+            None
+        } else if self.check_and_consume(&[TokenType::Var]) {
+            Some(self.parse_var_declaration()?)
+        } else {
+            Some(self.parse_expression_statement()?)
+        };
+
+        let condition = if !self.check(TokenType::Semicolon) {
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+        let semicolon = self.expect(TokenType::Semicolon, "Expected ';' after loop condition.")?;
+
+        let increment = if !self.check(TokenType::RightParen) {
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+        self.expect(TokenType::RightParen, "Expected ')' after for clauses.")?;
+
+        let mut body = self.nested(Self::parse_statement)?;
+
+        // The increment runs after the body on every iteration
+        if let Some(increment) = increment {
+            body = Statement::Block(vec![body, Statement::Expression(increment)]);
+        }
+
+        // An omitted condition loops forever
+        let condition = condition.unwrap_or(Expression::Literal {
+            value: Literal::Boolean(true),
+            span: semicolon.span(),
+        });
+        body = Statement::While {
+            keyword,
+            condition,
+            body: Box::new(body),
+        };
+
+        // The initialiser runs once, in a scope enclosing the whole loop
+        if let Some(initializer) = initializer {
+            body = Statement::Block(vec![initializer, body]);
+        }
+
+        Ok(body)
+    }
+
+    fn parse_if_statement(&mut self) -> ParseResult<Statement> {
+        self.expect(TokenType::LeftParen, "Expected '(' after 'if'.")?;
+        let condition = self.parse_expression()?;
+        self.expect(TokenType::RightParen, "Expected ')' after if condition.")?;
+        let then_branch = self.nested(Self::parse_statement)?;
+
+        // An `else` belongs to the nearest `if` before it. There is no special form of `else if`,
+        // it is just an `else` whose statement is another `if`.
+        let else_branch = if self.check_and_consume(&[TokenType::Else]) {
+            Some(Box::new(self.nested(Self::parse_statement)?))
+        } else {
+            None
+        };
+
+        Ok(Statement::If {
+            condition,
+            then_branch: Box::new(then_branch),
+            else_branch,
+        })
+    }
+
+    fn parse_return_statement(&mut self) -> ParseResult<Statement> {
+        let keyword = self.peek_previous();
+
+        let value = if !self.check(TokenType::Semicolon) {
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+
+        self.expect(TokenType::Semicolon, "Expected ';' after return value.")?;
+        Ok(Statement::Return { keyword, value })
+    }
+
+    fn parse_while_statement(&mut self) -> ParseResult<Statement> {
+        let keyword = self.peek_previous();
+        self.expect(TokenType::LeftParen, "Expected '(' after 'while'.")?;
+        let condition = self.parse_expression()?;
+        self.expect(TokenType::RightParen, "Expected ')' after condition.")?;
+        let body = self.nested(Self::parse_statement)?;
+
+        Ok(Statement::While {
+            keyword,
+            condition,
+            body: Box::new(body),
+        })
+    }
+
+    fn parse_print_statement(&mut self) -> ParseResult<Statement> {
+        let keyword = self.peek_previous();
+        // Commas separate the values to print, so a comma expression must be parenthesised
+        let mut values = vec![self.parse_assignment()?];
+        while self.check_and_consume(&[TokenType::Comma]) {
+            values.push(self.parse_assignment()?);
+        }
+        self.expect(TokenType::Semicolon, "Expected ';' after value.")?;
+        Ok(Statement::Print {
+            keyword,
+            expressions: values,
+        })
+    }
+
+    fn parse_expression_statement(&mut self) -> ParseResult<Statement> {
+        let expression = self.parse_expression()?;
+        if self.allow_bare_expression && self.is_at_end() {
+            return Ok(Statement::Expression(expression));
+        }
+        self.expect(TokenType::Semicolon, "Expected ';' after expression.")?;
+        Ok(Statement::Expression(expression))
     }
 
     fn parse_expression(&mut self) -> ParseResult<Expression> {
-        self.parse_equality()
+        self.parse_comma()
+    }
+
+    /// Parse a sequence of comma separated expressions, which evaluates to the last of them
+    fn parse_comma(&mut self) -> ParseResult<Expression> {
+        let expression = self.parse_assignment()?;
+        if !self.check(TokenType::Comma) {
+            return Ok(expression);
+        }
+
+        let mut expressions = vec![expression];
+        while self.check_and_consume(&[TokenType::Comma]) {
+            expressions.push(self.parse_assignment()?);
+        }
+
+        Ok(Expression::Comma { expressions })
+    }
+
+    fn parse_assignment(&mut self) -> ParseResult<Expression> {
+        let expression = self.parse_ternary()?;
+
+        if self.check_and_consume(&[TokenType::Equal]) {
+            let equals = self.peek_previous();
+            // Assignment is right-associative, so recurse rather than loop
+            let value = self.nested(Self::parse_assignment)?;
+
+            match expression {
+                Expression::Variable { name } => {
+                    return Ok(Expression::Assign {
+                        name,
+                        value: Box::new(value),
+                    })
+                }
+                // Assigning to a property turns the get into a set
+                Expression::Get { object, name } => {
+                    return Ok(Expression::Set {
+                        object,
+                        name,
+                        value: Box::new(value),
+                    })
+                }
+                // Likewise for assigning to an element of a list
+                Expression::Index {
+                    collection,
+                    bracket,
+                    index,
+                    ..
+                } => {
+                    return Ok(Expression::IndexSet {
+                        collection,
+                        bracket,
+                        index,
+                        value: Box::new(value),
+                    })
+                }
+                _ => {}
+            }
+
+            // The parser is not in a confused state, so report without unwinding to synchronise
+            self.error(&equals, "Invalid assignment target.".to_string());
+        } else if self.check_and_consume(&[
+            TokenType::PlusEqual,
+            TokenType::MinusEqual,
+            TokenType::StarEqual,
+            TokenType::SlashEqual,
+        ]) {
+            let compound = self.peek_previous();
+            let value = self.nested(Self::parse_assignment)?;
+
+            let Expression::Variable { name } = expression else {
+                self.error(&compound, "Invalid assignment target.".to_string());
+                return Ok(expression);
+            };
+
+            let (token_type, lexeme) = match compound.token_type {
+                TokenType::PlusEqual => (TokenType::Plus, "+"),
+                TokenType::MinusEqual => (TokenType::Minus, "-"),
+                TokenType::StarEqual => (TokenType::Star, "*"),
+                _ => (TokenType::Slash, "/"),
+            };
+            return Ok(Self::update_variable(
+                name, &compound, token_type, lexeme, value,
+            ));
+        }
+
+        Ok(expression)
+    }
+
+    /// Desugar an update of a variable, like `x += value`, into `x = x op value`. The operator
+    /// token is made up from `token_type` and `lexeme` at the position of `at`.
+    fn update_variable(
+        name: Token,
+        at: &Token,
+        token_type: TokenType,
+        lexeme: &str,
+        value: Expression,
+    ) -> Expression {
+        let operator = Token::new(
+            token_type,
+            lexeme.to_string(),
+            Literal::None,
+            at.line,
+            at.column,
+            at.offset,
+        );
+
+        Expression::Assign {
+            name: name.clone(),
+            value: Box::new(Expression::Binary {
+                left: Box::new(Expression::Variable { name }),
+                operator,
+                right: Box::new(value),
+            }),
+        }
+    }
+
+    fn parse_ternary(&mut self) -> ParseResult<Expression> {
+        let condition = self.parse_or()?;
+
+        if self.check_and_consume(&[TokenType::Question]) {
+            let then_expr = self.nested(Self::parse_expression)?;
+            self.expect(
+                TokenType::Colon,
+                "Expected ':' after then branch of conditional expression.",
+            )?;
+            // The conditional operator is right-associative, so recurse rather than loop
+            let else_expr = self.nested(Self::parse_ternary)?;
+
+            return Ok(Expression::Ternary {
+                condition: Box::new(condition),
+                then_expr: Box::new(then_expr),
+                else_expr: Box::new(else_expr),
+            });
+        }
+
+        Ok(condition)
+    }
+
+    fn parse_or(&mut self) -> ParseResult<Expression> {
+        let mut expression = self.parse_and()?;
+
+        while self.check_and_consume(&[TokenType::Or]) {
+            let operator = self.peek_previous();
+            let right = self.parse_and()?;
+            expression = Expression::Logical {
+                left: Box::new(expression),
+                operator,
+                right: Box::new(right),
+            }
+        }
+
+        Ok(expression)
+    }
+
+    fn parse_and(&mut self) -> ParseResult<Expression> {
+        let mut expression = self.parse_equality()?;
+
+        while self.check_and_consume(&[TokenType::And]) {
+            let operator = self.peek_previous();
+            let right = self.parse_equality()?;
+            expression = Expression::Logical {
+                left: Box::new(expression),
+                operator,
+                right: Box::new(right),
+            }
+        }
+
+        Ok(expression)
     }
 
     fn parse_equality(&mut self) -> ParseResult<Expression> {
@@ -48,14 +530,41 @@ impl Parser {
     fn parse_comparison(&mut self) -> ParseResult<Expression> {
         let mut expression = self.parse_term()?;
 
-        while self.check_and_consume(&[
+        let comparisons = [
             TokenType::Greater,
             TokenType::GreaterEqual,
             TokenType::Less,
             TokenType::LessEqual,
-        ]) {
+        ];
+        // A longer chain is the same mistake, so it is only reported once
+        let mut reported_chain = false;
+
+        while self.check_and_consume(&comparisons) {
             let operator = self.peek_previous();
             let right = self.parse_term()?;
+
+            // `1 < x < 3` would compare a boolean with a number, so point to the intended form
+            if let Expression::Binary {
+                left: first,
+                operator: first_operator,
+                right: middle,
+            } = &expression
+            {
+                if !reported_chain && comparisons.contains(&first_operator.token_type) {
+                    let message = format!(
+                        "Comparisons can't be chained. Use '{} {} {} and {} {} {}' instead.",
+                        first.to_source(),
+                        first_operator.lexeme,
+                        middle.to_source(),
+                        middle.to_source(),
+                        operator.lexeme,
+                        right.to_source(),
+                    );
+                    self.error(&operator, message);
+                    reported_chain = true;
+                }
+            }
+
             expression = Expression::Binary {
                 left: Box::new(expression),
                 operator,
@@ -85,7 +594,7 @@ impl Parser {
     fn parse_factor(&mut self) -> ParseResult<Expression> {
         let mut expression = self.parse_unary()?;
 
-        while self.check_and_consume(&[TokenType::Slash, TokenType::Star]) {
+        while self.check_and_consume(&[TokenType::Slash, TokenType::Star, TokenType::Percent]) {
             let operator = self.peek_previous();
             let right = self.parse_unary()?;
             expression = Expression::Binary {
@@ -99,26 +608,180 @@ impl Parser {
     }
 
     fn parse_unary(&mut self) -> ParseResult<Expression> {
-        if self.check_and_consume(&[TokenType::Bang, TokenType::Minus]) {
+        if self.check_and_consume(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
             let operator = self.peek_previous();
-            let right = self.parse_unary()?;
+            let operand = self.nested(Self::parse_unary)?;
+
+            let Expression::Variable { name } = operand else {
+                self.error(
+                    &operator,
+                    format!("Operand of '{}' must be a variable.", operator.lexeme),
+                );
+                return Ok(operand);
+            };
+
+            // `++x` is sugar for `x = x + 1`, and likewise for `--x`
+            let (token_type, lexeme) = if operator.token_type == TokenType::PlusPlus {
+                (TokenType::Plus, "+")
+            } else {
+                (TokenType::Minus, "-")
+            };
+            let one = Expression::Literal {
+                value: Literal::Number(1.0),
+                span: operator.span(),
+            };
+            Ok(Self::update_variable(
+                name, &operator, token_type, lexeme, one,
+            ))
+        } else if self.check_and_consume(&[TokenType::Bang, TokenType::Minus]) {
+            let operator = self.peek_previous();
+            let right = self.nested(Self::parse_unary)?;
             Ok(Expression::Unary {
                 operator,
                 right: Box::new(right),
             })
         } else {
-            self.parse_literal_or_group()
+            self.parse_power()
+        }
+    }
+
+    fn parse_power(&mut self) -> ParseResult<Expression> {
+        let expression = self.parse_call()?;
+
+        // Exponents are right-associative, so `2 ** 3 ** 2` is `2 ** (3 ** 2)`. The exponent may
+        // itself be negated, as in `2 ** -1`, but `-2 ** 2` negates the result.
+        if self.check_and_consume(&[TokenType::StarStar]) {
+            let operator = self.peek_previous();
+            let right = self.nested(Self::parse_unary)?;
+            return Ok(Expression::Binary {
+                left: Box::new(expression),
+                operator,
+                right: Box::new(right),
+            });
+        }
+
+        Ok(expression)
+    }
+
+    fn parse_call(&mut self) -> ParseResult<Expression> {
+        let mut expression = self.parse_literal_or_group()?;
+
+        // Calls, property accesses and indexing can be chained, as in `f(1)(2)` or `a.b()[0].c`
+        loop {
+            if self.check_and_consume(&[TokenType::LeftParen]) {
+                expression = self.finish_call(expression)?;
+            } else if self.check_and_consume(&[TokenType::Dot]) {
+                let name =
+                    self.expect(TokenType::Identifier, "Expected property name after '.'.")?;
+                expression = Expression::Get {
+                    object: Box::new(expression),
+                    name,
+                };
+            } else if self.check_and_consume(&[TokenType::LeftBracket]) {
+                let bracket = self.peek_previous();
+                let index = self.nested(Self::parse_expression)?;
+                let closing = self.expect(TokenType::RightBracket, "Expected ']' after index.")?;
+                expression = Expression::Index {
+                    span: expression.span().to(closing.span()),
+                    collection: Box::new(expression),
+                    bracket,
+                    index: Box::new(index),
+                };
+            } else {
+                break;
+            }
         }
+
+        Ok(expression)
+    }
+
+    /// Parse the argument list of a call whose opening parenthesis has been consumed
+    fn finish_call(&mut self, callee: Expression) -> ParseResult<Expression> {
+        let mut arguments = Vec::new();
+
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    // Report without unwinding, since the parser is still in a valid state
+                    self.error(
+                        &self.peek(),
+                        format!("Can't have more than {MAX_ARGUMENTS} arguments."),
+                    );
+                }
+                // Commas here separate arguments, so skip the sequence operator
+                arguments.push(self.nested(Self::parse_assignment)?);
+                if !self.check_and_consume(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        let paren = self.expect(TokenType::RightParen, "Expected ')' after arguments.")?;
+        Ok(Expression::Call {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        })
     }
 
     fn parse_literal_or_group(&mut self) -> ParseResult<Expression> {
-        let curr_literal = self.peek().literal;
+        if self.check_and_consume(&[TokenType::LeftParen]) {
+            let opening = self.previous_token().span();
+            let expression = self.nested(Self::parse_expression)?;
+            let closing = self.expect(TokenType::RightParen, "Expected ')' after expression.")?;
+            return Ok(Expression::Grouping {
+                expression: Box::new(expression),
+                span: opening.to(closing.span()),
+            });
+        }
+
+        if self.check_and_consume(&[TokenType::LeftBracket]) {
+            let opening = self.previous_token().span();
+            let mut elements = Vec::new();
+            if !self.check(TokenType::RightBracket) {
+                loop {
+                    // Commas here separate elements, so skip the sequence operator
+                    elements.push(self.nested(Self::parse_assignment)?);
+                    if !self.check_and_consume(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            let closing =
+                self.expect(TokenType::RightBracket, "Expected ']' after list elements.")?;
+            return Ok(Expression::ListLiteral {
+                elements,
+                span: opening.to(closing.span()),
+            });
+        }
+
+        if self.check_and_consume(&[TokenType::Fun]) {
+            let keyword = self.peek_previous();
+            self.expect(TokenType::LeftParen, "Expected '(' after 'fun'.")?;
+            let declaration = self.finish_function(None, "function")?;
+            return Ok(Expression::Lambda {
+                span: keyword.span().to(self.previous_token().span()),
+                keyword,
+                declaration: Rc::new(declaration),
+            });
+        }
+
+        if self.check_and_consume(&[TokenType::Super]) {
+            let keyword = self.peek_previous();
+            self.expect(TokenType::Dot, "Expected '.' after 'super'.")?;
+            let method = self.expect(TokenType::Identifier, "Expected superclass method name.")?;
+            return Ok(Expression::Super { keyword, method });
+        }
+
+        let curr_literal = self.current_token().literal.clone();
+        let span = self.current_token().span();
 
-        let match_result = match self.peek().token_type {
+        let match_result = match self.current_token().token_type {
             TokenType::False | TokenType::True => {
                 if let Literal::Boolean(bool) = curr_literal {
                     Ok(Expression::Literal {
                         value: Literal::Boolean(bool),
+                        span,
                     })
                 } else {
                     Err(ParseError::new(format!(
@@ -130,12 +793,20 @@ impl Parser {
 
             TokenType::Nil => Ok(Expression::Literal {
                 value: Literal::None,
+                span,
+            }),
+
+            TokenType::Identifier => Ok(Expression::Variable { name: self.peek() }),
+
+            TokenType::This => Ok(Expression::This {
+                keyword: self.peek(),
             }),
 
             TokenType::Number => {
                 if let Literal::Number(num) = curr_literal {
                     Ok(Expression::Literal {
                         value: Literal::Number(num),
+                        span,
                     })
                 } else {
                     Err(ParseError::new(format!(
@@ -149,6 +820,7 @@ impl Parser {
                 if let Literal::String(str) = curr_literal {
                     Ok(Expression::Literal {
                         value: Literal::String(str),
+                        span,
                     })
                 } else {
                     Err(ParseError::new(format!(
@@ -158,52 +830,119 @@ impl Parser {
                 }
             }
 
-            TokenType::LeftParen => {
-                let expression = self.parse_expression()?;
-                if self.check_and_consume(&[TokenType::RightParen]) {
-                    Ok(Expression::Grouping {
-                        expression: Box::new(expression),
-                    })
-                } else {
-                    Err(ParseError::new(
-                        "Expected ')' after expression.".to_string(),
-                    ))
-                }
-            }
-
-            _ => Err(ParseError::new(format!(
-                "Token '{}' parsing was unhandled.",
-                self.peek().lexeme
-            ))),
+            _ => Err(ParseError::new("Expected expression.".to_string())),
         };
 
         if match_result.is_ok() {
-            self.consume();
+            self.advance();
         }
 
         match_result
     }
 
+    fn error(&mut self, token: &Token, message: String) {
+        self.diagnostics.push(Diagnostic::parse(token, message));
+    }
+
+    /// Parse with the given function one level further nested, failing once the nesting gets too
+    /// deep. Each statement and expression which may contain another of its kind is parsed
+    /// through here.
+    fn nested<T>(&mut self, parse: fn(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        if self.depth >= MAX_NESTING {
+            return Err(ParseError::new("Too much nesting.".to_string()));
+        }
+
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Consume the current token if it is of the expected type, otherwise fail with the given
+    /// message
+    fn expect(&mut self, token_type: TokenType, message: &str) -> ParseResult<Token> {
+        if self.check(token_type) {
+            Ok(self.consume())
+        } else {
+            Err(ParseError::new(message.to_string()))
+        }
+    }
+
+    /// Consume the name of something being declared, where `kind` describes it. A keyword is
+    /// reported but parsing carries on as the user intended: a keyword in front of the name, as
+    /// in `fun foo()` inside a class, is skipped, and any other keyword is taken as the name.
+    fn expect_name(&mut self, kind: &str, message: &str) -> ParseResult<Token> {
+        let token = self.peek();
+        if !token.token_type.is_keyword() {
+            return self.expect(TokenType::Identifier, message);
+        }
+
+        self.error(
+            &token,
+            format!("Can't use keyword '{}' as a {kind} name.", token.lexeme),
+        );
+        let keyword = self.consume();
+        if self.check(TokenType::Identifier) {
+            Ok(self.consume())
+        } else {
+            Ok(keyword)
+        }
+    }
+
+    /// Whether the current token is of the given type
+    fn check(&self, token_type: TokenType) -> bool {
+        self.current_token().token_type == token_type
+    }
+
+    /// Whether the token after the current one is of the given type
+    fn check_next(&self, token_type: TokenType) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|token| token.token_type == token_type)
+    }
+
     fn peek_previous(&self) -> Token {
+        self.previous_token().clone()
+    }
+
+    /// Borrow the current token, avoiding the clone made by `peek`
+    fn current_token(&self) -> &Token {
         self.tokens
-            .get(self.current - 1)
+            .get(self.current)
             .unwrap_or_else(|| panic!("Failed to get token at index {}", self.current))
-            .clone()
+    }
+
+    fn previous_token(&self) -> &Token {
+        let index = self
+            .current
+            .checked_sub(1)
+            .expect("Cannot peek the previous token before any tokens have been consumed");
+        self.tokens
+            .get(index)
+            .unwrap_or_else(|| panic!("Failed to get token at index {}", index))
+    }
+
+    /// Move on to the next token without cloning the current one, as `consume` does
+    fn advance(&mut self) {
+        // Never advance past the end of input token
+        if !self.is_at_end() {
+            self.current += 1;
+        }
     }
 
     /// Given some invalid syntax, discard the invalid parts until we are left with only valid
     /// syntax so we can continue parsing and check other parts of the code.
     fn synchronise(&mut self) {
-        self.consume();
+        self.advance();
 
         while !self.is_at_end() {
-            if self.peek_previous().token_type == TokenType::Semicolon {
+            if self.previous_token().token_type == TokenType::Semicolon {
                 return;
             }
 
             // If we hit a token of one of these types, we can essentially "restart" parsing as if
             // we did not encounter an error
-            match self.peek().token_type {
+            match self.current_token().token_type {
                 TokenType::Class
                 | TokenType::Fun
                 | TokenType::Var
@@ -213,7 +952,7 @@ impl Parser {
                 | TokenType::Print
                 | TokenType::Return => return,
                 _ => {
-                    self.consume();
+                    self.advance();
                 }
             }
         }
@@ -222,21 +961,21 @@ impl Parser {
 
 impl GenericScanner<Token> for Parser {
     fn is_at_end(&self) -> bool {
-        self.peek().token_type == TokenType::Eof
+        self.check(TokenType::Eof)
     }
 
     fn consume(&mut self) -> Token {
         let token = self.peek();
-        self.current += 1;
+        self.advance();
         token
     }
 
     fn check_and_consume<MatchType: PartialEq<Token>>(&mut self, expected: &[MatchType]) -> bool {
         if expected
             .iter()
-            .any(|expected_token_type| *expected_token_type == self.peek())
+            .any(|expected_token_type| *expected_token_type == *self.current_token())
         {
-            self.consume();
+            self.advance();
             true
         } else {
             false
@@ -244,10 +983,7 @@ impl GenericScanner<Token> for Parser {
     }
 
     fn peek(&self) -> Token {
-        self.tokens
-            .get(self.current)
-            .unwrap_or_else(|| panic!("Failed to get token at index {}", self.current))
-            .clone()
+        self.current_token().clone()
     }
 
     fn peek_next(&self) -> Token {
@@ -257,3 +993,315 @@ impl GenericScanner<Token> for Parser {
             .clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Parser;
+    use crate::expression::Expression;
+    use crate::parse_source;
+    use crate::scanner::Scanner;
+    use crate::statement::Statement;
+    use crate::test_util::{errors_of, on_large_stack, output_of};
+    use crate::token::{Literal, TokenType};
+
+    fn parse(source: &str) -> Vec<Statement> {
+        let tokens = Scanner::new(source.to_string())
+            .scan_tokens()
+            .expect("source should scan");
+        Parser::new(tokens).parse().expect("source should parse")
+    }
+
+    #[test]
+    fn desugars_for_loops_into_while_loops() {
+        let statements = parse("for (var i = 0; i < 3; i = i + 1) print i;");
+
+        let [Statement::Block(outer)] = statements.as_slice() else {
+            panic!("the loop should be wrapped in a block for its initialiser");
+        };
+        let [Statement::Var { name, .. }, Statement::While {
+            condition, body, ..
+        }] = outer.as_slice()
+        else {
+            panic!("the initialiser should run before the while loop");
+        };
+        assert_eq!(name.lexeme, "i");
+        assert!(matches!(condition, Expression::Binary { .. }));
+        let Statement::Block(inner) = body.as_ref() else {
+            panic!("the body should be wrapped in a block with the increment");
+        };
+        assert!(matches!(
+            inner.as_slice(),
+            [
+                Statement::Print { .. },
+                Statement::Expression(Expression::Assign { .. })
+            ]
+        ));
+    }
+
+    #[test]
+    fn allows_each_for_clause_to_be_omitted() {
+        let statements = parse("for (;;) print 1;");
+
+        let [Statement::While {
+            condition, body, ..
+        }] = statements.as_slice()
+        else {
+            panic!("a loop without an initialiser should not be wrapped in a block");
+        };
+        let Expression::Literal { value, .. } = condition else {
+            panic!("an omitted condition should be a literal");
+        };
+        assert!(*value == Literal::Boolean(true));
+        assert!(matches!(body.as_ref(), Statement::Print { .. }));
+    }
+
+    #[test]
+    fn reports_an_error_on_each_bad_line() {
+        let tokens = Scanner::new("var = 1;\nprint (1 + ;\nvar b = 2\nprint b;\n".to_string())
+            .scan_tokens()
+            .expect("source should scan");
+        let diagnostics = match Parser::new(tokens).parse() {
+            Ok(_) => panic!("Program parsed without errors"),
+            Err(diagnostics) => diagnostics,
+        };
+
+        let lines = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.line)
+            .collect::<Vec<_>>();
+        // The missing semicolon is only noticed at the start of the next line
+        assert_eq!(lines, [1, 2, 4]);
+    }
+
+    #[test]
+    fn parses_large_programs() {
+        let terms = (1..=1000).map(|i| i.to_string()).collect::<Vec<_>>();
+        let mut source = format!("var sum = {};\n", terms.join(" + "));
+        source.push_str(&"sum = sum - 1;\n".repeat(1000));
+        source.push_str("print sum;\n");
+
+        assert_eq!(parse(&source).len(), 1002);
+        assert_eq!(on_large_stack(move || output_of(&source)), "499500\n");
+    }
+
+    #[test]
+    fn rejects_deeply_nested_input_without_overflowing_the_stack() {
+        let depth = 100_000;
+        let sources = [
+            format!("print {}1{};", "(".repeat(depth), ")".repeat(depth)),
+            format!("print {}true;", "!".repeat(depth)),
+            format!("print {}1{};", "[".repeat(depth), "]".repeat(depth)),
+            format!("print {}nil;", "false ? 1 : ".repeat(depth)),
+            format!("{}{}", "{".repeat(depth), "}".repeat(depth)),
+        ];
+        for source in sources {
+            assert_eq!(errors_of(&source), ["Too much nesting."]);
+        }
+    }
+
+    #[test]
+    fn runs_input_nested_up_to_the_limit() {
+        let depth = super::MAX_NESTING - 1;
+        let source = format!("{{ print {}1{}; }}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(output_of(&source), "1\n");
+    }
+
+    #[test]
+    fn desugars_compound_assignment() {
+        let source =
+            "var x = 10; print x += 2; print x; x -= 4; print x; x *= 3; print x; x /= 4; print x;";
+        assert_eq!(output_of(source), "12\n12\n8\n24\n6\n");
+    }
+
+    #[test]
+    fn leaves_anonymous_functions_unnamed() {
+        let statements = parse("var f = fun (a) { return a; };");
+        let [Statement::Var {
+            initializer:
+                Some(Expression::Lambda {
+                    keyword,
+                    declaration,
+                    ..
+                }),
+            ..
+        }] = statements.as_slice()
+        else {
+            panic!("Expected a variable initialised with an anonymous function");
+        };
+        assert_eq!(keyword.token_type, TokenType::Fun);
+        assert!(declaration.name.is_none());
+
+        let statements = parse("fun f() {}");
+        let [Statement::Function(declaration)] = statements.as_slice() else {
+            panic!("Expected a function declaration");
+        };
+        assert_eq!(declaration.declared_name().lexeme, "f");
+    }
+
+    #[test]
+    fn reports_a_missing_expression() {
+        assert_eq!(errors_of("var x = ;"), ["Expected expression."]);
+        assert_eq!(errors_of("print 1 + ;"), ["Expected expression."]);
+    }
+
+    #[test]
+    fn rejects_compound_assignment_to_non_variables() {
+        assert_eq!(errors_of("1 += 2;"), ["Invalid assignment target."]);
+        assert_eq!(
+            errors_of("var a; (a) *= 2;"),
+            ["Invalid assignment target."]
+        );
+    }
+
+    #[test]
+    fn desugars_prefix_increment_and_decrement() {
+        let source = "var i = 5; print ++i; print i; print --i; print --i; print i;";
+        assert_eq!(output_of(source), "6\n6\n5\n4\n4\n");
+    }
+
+    #[test]
+    fn rejects_incrementing_non_variables() {
+        assert_eq!(errors_of("++5;"), ["Operand of '++' must be a variable."]);
+        assert_eq!(
+            errors_of("var a; --(a);"),
+            ["Operand of '--' must be a variable."]
+        );
+    }
+
+    #[test]
+    fn suggests_and_for_chained_comparisons() {
+        assert_eq!(
+            errors_of("var x = 2; print 1 < x < 3;"),
+            ["Comparisons can't be chained. Use '1 < x and x < 3' instead."]
+        );
+        assert_eq!(
+            errors_of("var x = 2; print 1 < x >= 3;"),
+            ["Comparisons can't be chained. Use '1 < x and x >= 3' instead."]
+        );
+        // Grouping makes the comparison of a boolean deliberate
+        assert_eq!(
+            errors_of("print (1 < 2) < 3;"),
+            ["Operands 'true' and '3' must both be numbers."]
+        );
+    }
+
+    #[test]
+    fn chains_else_if_without_braces() {
+        let source = r#"
+            fun sign(n) {
+                if (n < 0) { return "negative"; } else if (n == 0) { return "zero"; } else { return "positive"; }
+            }
+            print sign(-3);
+            print sign(0);
+            print sign(7);
+        "#;
+        assert_eq!(output_of(source), "negative\nzero\npositive\n");
+    }
+
+    #[test]
+    fn parses_each_kind_of_statement() {
+        let source = r#"
+            var a = (1 + 2) * -3;
+            if (a > 0 and !false) print "x"; else a = nil;
+            while (a < 10) { a = a + 1; }
+            fun f(x, y) { return x.y(y)[0]; }
+            class B < A { init() { super.init(this); } }
+        "#;
+        let trees = parse_source(source)
+            .unwrap_or_default()
+            .iter()
+            .map(|statement| statement.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            trees,
+            [
+                "(var a (* (group (+ 1 2)) (- 3)))",
+                "(if (and (> a 0) (! false)) (print x) (; (= a nil)))",
+                "(while (< a 10) (block (; (= a (+ a 1)))))",
+                "(fun f (x y) (return (index (call (. y x) y) 0)))",
+                "(class B < A (fun init () (; (call (super init) this))))",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_truncated_input_without_panicking() {
+        for source in [
+            "print",
+            "(",
+            "fun",
+            "class A {",
+            "a.",
+            "var",
+            "1 +",
+            "f(1,",
+            "{",
+        ] {
+            let diagnostics = parse_source(source).err().unwrap_or_default();
+            assert_eq!(diagnostics.len(), 1, "{source}");
+            assert_eq!(
+                diagnostics[0].location.as_deref(),
+                Some("at end of input"),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_token_lists_without_an_end_of_input_token() {
+        assert!(Parser::new(Vec::new())
+            .parse()
+            .is_ok_and(|statements| statements.is_empty()));
+
+        let mut tokens = Scanner::new("print 1;".to_string())
+            .scan_tokens()
+            .unwrap_or_default();
+        tokens.pop();
+        assert_eq!(Parser::new(tokens).parse().map(|s| s.len()).ok(), Some(1));
+    }
+
+    #[test]
+    fn rejects_keywords_as_names() {
+        let cases = [
+            (
+                "var class = 1;",
+                "Can't use keyword 'class' as a variable name.",
+            ),
+            (
+                "var this = 2;",
+                "Can't use keyword 'this' as a variable name.",
+            ),
+            (
+                "fun while() {}",
+                "Can't use keyword 'while' as a function name.",
+            ),
+            (
+                "fun f(a, nil) {}",
+                "Can't use keyword 'nil' as a parameter name.",
+            ),
+            (
+                "class print {}",
+                "Can't use keyword 'print' as a class name.",
+            ),
+            (
+                "class A { return() {} }",
+                "Can't use keyword 'return' as a method name.",
+            ),
+        ];
+        for (source, message) in cases {
+            assert_eq!(errors_of(source), [message], "{source}");
+        }
+    }
+
+    #[test]
+    fn reports_a_keyword_method_name_once() {
+        assert_eq!(
+            errors_of("class A { fun foo() {} } print 1;"),
+            ["Can't use keyword 'fun' as a method name."]
+        );
+        assert_eq!(
+            errors_of("fun while(a) { return a; } print 1;"),
+            ["Can't use keyword 'while' as a function name."]
+        );
+    }
+}
@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+
+use crate::error::Diagnostic;
+use crate::expression::Expression;
+use crate::interpreter::Interpreter;
+use crate::statement::{FunctionDeclaration, Statement};
+use crate::symbol::Symbol;
+use crate::token::{Span, Token};
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+/// Static pass run between parsing and interpreting that works out which scope every local
+/// variable use refers to, recording the number of scopes between the use and its binding in the
+/// interpreter. Variables that are not found in any local scope are assumed to be globals.
+pub struct Resolver<'a> {
+    interpreter: &'a mut Interpreter,
+    /// Stack of local scopes, mapping each declared name to whether its initialiser has finished
+    scopes: Vec<HashMap<Symbol, bool>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    diagnostics: Vec<Diagnostic>,
+    /// Likely mistakes that don't stop the program from running, such as unreachable code
+    warnings: Vec<Diagnostic>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(interpreter: &'a mut Interpreter) -> Self {
+        Resolver {
+            interpreter,
+            scopes: Vec::new(),
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            diagnostics: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn resolve(&mut self, statements: &[Statement]) -> Result<(), Vec<Diagnostic>> {
+        self.warnings.clear();
+        self.resolve_statements(statements);
+
+        if self.diagnostics.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.diagnostics))
+        }
+    }
+
+    /// Warnings found by the last call to `resolve`, which are reported whether or not it
+    /// succeeded
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    fn resolve_statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.resolve_statement(statement);
+        }
+
+        // Statements after a return in the same block can never run. Only the first is reported,
+        // skipping empty blocks as they hold no code.
+        let unreachable = statements
+            .iter()
+            .skip_while(|statement| !matches!(statement, Statement::Return { .. }))
+            .skip(1)
+            .find_map(statement_span);
+        if let Some(span) = unreachable {
+            self.warnings.push(Diagnostic::resolve_warning(
+                span,
+                "Unreachable code after return.".to_string(),
+            ));
+        }
+    }
+
+    fn resolve_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Block(statements) => {
+                self.begin_scope();
+                self.resolve_statements(statements);
+                self.end_scope();
+            }
+            Statement::Expression(expression) => self.resolve_expression(expression),
+            Statement::Print { expressions, .. } => {
+                for expression in expressions {
+                    self.resolve_expression(expression);
+                }
+            }
+            Statement::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let enclosing_class = self.current_class;
+                self.current_class = ClassType::Class;
+
+                self.declare(name);
+                self.define(name);
+
+                if let Some(superclass) = superclass {
+                    if let Expression::Variable {
+                        name: superclass_name,
+                    } = superclass
+                    {
+                        if superclass_name.lexeme == name.lexeme {
+                            self.error(superclass_name, "A class can't inherit from itself.");
+                        }
+                    }
+
+                    self.current_class = ClassType::Subclass;
+                    self.resolve_expression(superclass);
+
+                    // Subclass methods sit inside a scope which defines `super`
+                    self.begin_scope();
+                    if let Some(scope) = self.scopes.last_mut() {
+                        scope.insert(Symbol::intern("super"), true);
+                    }
+                }
+
+                // Methods are bound in a scope of their own which defines `this`
+                self.begin_scope();
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert(Symbol::intern("this"), true);
+                }
+                for method in methods {
+                    let kind = if method.declared_name().lexeme == "init" {
+                        FunctionType::Initializer
+                    } else {
+                        FunctionType::Method
+                    };
+                    self.resolve_function(method, kind);
+                }
+                self.end_scope();
+
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+
+                self.current_class = enclosing_class;
+            }
+            Statement::Function(declaration) => {
+                // Define the name before resolving the body so the function can recurse
+                self.declare(declaration.declared_name());
+                self.define(declaration.declared_name());
+                self.resolve_function(declaration, FunctionType::Function);
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expression(condition);
+                self.resolve_statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_statement(else_branch);
+                }
+            }
+            Statement::Return { keyword, value } => {
+                if self.current_function == FunctionType::None {
+                    self.error(keyword, "Can't return from top-level code.");
+                }
+                if let Some(value) = value {
+                    if self.current_function == FunctionType::Initializer {
+                        self.error(keyword, "Can't return a value from an initializer.");
+                    }
+                    self.resolve_expression(value);
+                }
+            }
+            Statement::Var { name, initializer } => {
+                // Declaring and defining separately catches initialisers that read the variable
+                self.declare(name);
+                if let Some(initializer) = initializer {
+                    self.resolve_expression(initializer);
+                }
+                self.define(name);
+            }
+            Statement::While {
+                condition, body, ..
+            } => {
+                self.resolve_expression(condition);
+                self.resolve_statement(body);
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, declaration: &FunctionDeclaration, kind: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = kind;
+
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_statements(&declaration.body);
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+    }
+
+    fn resolve_expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Assign { name, value } => {
+                self.resolve_expression(value);
+                self.resolve_local(name);
+            }
+            Expression::Binary { left, right, .. } | Expression::Logical { left, right, .. } => {
+                self.resolve_expression(left);
+                self.resolve_expression(right);
+            }
+            Expression::Call {
+                callee, arguments, ..
+            } => {
+                self.resolve_expression(callee);
+                for argument in arguments {
+                    self.resolve_expression(argument);
+                }
+            }
+            Expression::Comma { expressions } => {
+                for expression in expressions {
+                    self.resolve_expression(expression);
+                }
+            }
+            Expression::Get { object, .. } => self.resolve_expression(object),
+            Expression::Index {
+                collection, index, ..
+            } => {
+                self.resolve_expression(collection);
+                self.resolve_expression(index);
+            }
+            Expression::IndexSet {
+                collection,
+                index,
+                value,
+                ..
+            } => {
+                self.resolve_expression(value);
+                self.resolve_expression(collection);
+                self.resolve_expression(index);
+            }
+            Expression::Lambda { declaration, .. } => {
+                self.resolve_function(declaration, FunctionType::Function)
+            }
+            Expression::ListLiteral { elements, .. } => {
+                for element in elements {
+                    self.resolve_expression(element);
+                }
+            }
+            Expression::Grouping { expression, .. } => self.resolve_expression(expression),
+            Expression::Literal { .. } => {}
+            Expression::Set { object, value, .. } => {
+                self.resolve_expression(value);
+                self.resolve_expression(object);
+            }
+            Expression::Super { keyword, .. } => {
+                match self.current_class {
+                    ClassType::None => self.error(keyword, "Can't use 'super' outside of a class."),
+                    ClassType::Class => {
+                        self.error(keyword, "Can't use 'super' in a class with no superclass.")
+                    }
+                    ClassType::Subclass => {}
+                }
+                self.resolve_local(keyword);
+            }
+            Expression::Ternary {
+                condition,
+                then_expr,
+                else_expr,
+            } => {
+                self.resolve_expression(condition);
+                self.resolve_expression(then_expr);
+                self.resolve_expression(else_expr);
+            }
+            Expression::This { keyword } => {
+                if self.current_class == ClassType::None {
+                    self.error(keyword, "Can't use 'this' outside of a class.");
+                }
+                self.resolve_local(keyword);
+            }
+            Expression::Unary { right, .. } => self.resolve_expression(right),
+            Expression::Variable { name } => {
+                let is_uninitialised = self
+                    .scopes
+                    .last()
+                    .and_then(|scope| scope.get(&name.symbol()))
+                    .is_some_and(|is_defined| !is_defined);
+                if is_uninitialised {
+                    self.error(name, "Can't read local variable in its own initializer.");
+                }
+
+                self.resolve_local(name);
+            }
+        }
+    }
+
+    /// Record how many scopes out from the innermost one the name is bound, leaving it
+    /// unresolved (global) if no local scope declares it
+    fn resolve_local(&mut self, name: &Token) {
+        if let Some(depth) = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&name.symbol()))
+        {
+            self.interpreter.resolve(name, depth);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+
+        if scope.insert(name.symbol(), false).is_some() {
+            self.error(name, "Already a variable with this name in this scope.");
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.symbol(), true);
+        }
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.diagnostics
+            .push(Diagnostic::resolve(token, message.to_string()));
+    }
+}
+
+/// The source covered by the start of a statement, or `None` for a block with nothing in it
+fn statement_span(statement: &Statement) -> Option<Span> {
+    match statement {
+        Statement::Block(statements) => statements.iter().find_map(statement_span),
+        Statement::Class { name, .. } | Statement::Var { name, .. } => Some(name.span()),
+        Statement::Expression(expression) => Some(expression.span()),
+        Statement::Function(declaration) => Some(declaration.declared_name().span()),
+        Statement::If { condition, .. } => Some(condition.span()),
+        Statement::Print { keyword, .. }
+        | Statement::Return { keyword, .. }
+        | Statement::While { keyword, .. } => Some(keyword.span()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Resolver;
+    use crate::error::{Diagnostic, Severity};
+    use crate::test_util::{output_of, values_of};
+    use crate::{parse_source, Interpreter, Value};
+
+    /// Warnings found in a program which resolves without errors
+    fn warnings_of(source: &str) -> Vec<Diagnostic> {
+        let statements = parse_source(source).expect("Program failed to parse");
+        let mut interpreter = Interpreter::new();
+        let mut resolver = Resolver::new(&mut interpreter);
+        if let Err(diagnostics) = resolver.resolve(&statements) {
+            panic!("Program failed to resolve: {:?}", diagnostics);
+        }
+        resolver.warnings().to_vec()
+    }
+
+    #[test]
+    fn closures_keep_the_binding_in_scope_when_declared() {
+        let source = r#"
+            var a = "global";
+            var first;
+            var second;
+            {
+                fun showA() { return a; }
+                first = showA();
+                var a = "block";
+                second = showA();
+            }
+            first; second;
+        "#;
+        let values = values_of(source);
+        assert!(
+            values
+                == [
+                    Value::String("global".into()),
+                    Value::String("global".into())
+                ]
+        );
+    }
+
+    #[test]
+    fn warns_at_the_first_statement_after_a_return() {
+        let warnings = warnings_of(
+            "fun f() {
+  return 1;
+  print 2;
+  print 3;
+}",
+        );
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].severity, Severity::Warning);
+        assert_eq!(warnings[0].message, "Unreachable code after return.");
+        assert_eq!((warnings[0].line, warnings[0].column), (3, Some(3)));
+    }
+
+    #[test]
+    fn warns_at_unreachable_literals() {
+        let warnings = warnings_of("fun f() { return; {} (1); }");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].line, warnings[0].column), (1, Some(22)));
+    }
+
+    #[test]
+    fn does_not_warn_without_code_after_a_return() {
+        assert!(warnings_of("fun f(x) { if (x) return 1; return 2; }").is_empty());
+        assert!(warnings_of("fun f() { return; {} }").is_empty());
+    }
+
+    #[test]
+    fn runs_programs_with_unreachable_code() {
+        assert_eq!(
+            output_of("fun f() { return 1; print 2; } print f();"),
+            "1\n"
+        );
+    }
+}
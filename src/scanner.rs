@@ -1,43 +1,84 @@
 use std::collections::HashMap;
 
-use crate::error::lox_generic_error;
+use crate::error::Diagnostic;
 use crate::token::{Literal, Token, TokenType};
 use crate::util::GenericScanner;
 
 use lazy_static::lazy_static;
 
 pub struct Scanner {
-    source: String,
-    tokens: Vec<Token>,
+    /// Source code split into characters up front so that indexing is constant time and
+    /// positions are always measured in characters rather than bytes
+    source: Vec<char>,
+    /// Token produced by the most recent call to `scan_token`, if it produced one
+    pending: Option<Token>,
+    /// Type of the last token yielded, which decides whether a `.` starts a number
+    previous_token_type: Option<TokenType>,
+    /// Whether the final `Eof` token has been yielded
+    finished: bool,
     start: usize,
     current: usize,
     line: usize,
+    column: usize,
+    /// Line the token being scanned starts on, which differs from `line` for a string that
+    /// spans several lines
+    start_line: usize,
+    start_column: usize,
+    /// Number of columns between tab stops, used when counting the column after a tab
+    tab_width: usize,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Self {
         Scanner {
-            source,
-            tokens: Vec::new(),
+            source: source.chars().collect(),
+            pending: None,
+            previous_token_type: None,
+            finished: false,
             start: 0,
             current: 0,
             line: 1,
+            column: 1,
+            start_line: 1,
+            start_column: 1,
+            tab_width: 1,
+            diagnostics: Vec::new(),
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Vec<Token> {
-        while !self.is_at_end() {
-            self.start = self.current;
-            self.scan_token()
+    /// Count a tab as moving to the next multiple of `tab_width` columns, as an editor that
+    /// expands tabs would show it, rather than as a single column. A width of 0 is treated as 1.
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width.max(1);
+    }
+
+    /// Scan the whole source, returning every token or every error that was found. Scanning
+    /// starts again from the beginning of the source each time, so calling this more than once
+    /// gives the same result, even after some tokens have been taken by iterating.
+    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, Vec<Diagnostic>> {
+        self.reset();
+        let tokens = self.by_ref().collect();
+
+        if self.diagnostics.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(std::mem::take(&mut self.diagnostics))
         }
+    }
 
-        self.tokens.push(Token::new(
-            TokenType::Eof,
-            "".to_string(),
-            Literal::None,
-            self.line,
-        ));
-        self.tokens.clone()
+    /// Go back to the beginning of the source, forgetting anything scanned so far
+    fn reset(&mut self) {
+        self.pending = None;
+        self.previous_token_type = None;
+        self.finished = false;
+        self.start = 0;
+        self.current = 0;
+        self.line = 1;
+        self.column = 1;
+        self.start_line = 1;
+        self.start_column = 1;
+        self.diagnostics.clear();
     }
 
     fn scan_token(&mut self) {
@@ -47,11 +88,43 @@ impl Scanner {
             ')' => self.add_token(TokenType::RightParen),
             '{' => self.add_token(TokenType::LeftBrace),
             '}' => self.add_token(TokenType::RightBrace),
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
             ',' => self.add_token(TokenType::Comma),
-            '-' => self.add_token(TokenType::Minus),
-            '+' => self.add_token(TokenType::Plus),
             ';' => self.add_token(TokenType::Semicolon),
-            '*' => self.add_token(TokenType::Star),
+            '%' => self.add_token(TokenType::Percent),
+            '?' => self.add_token(TokenType::Question),
+            ':' => self.add_token(TokenType::Colon),
+            '-' => {
+                let token_type = if self.check_and_consume(&['=']) {
+                    TokenType::MinusEqual
+                } else if self.check_and_consume(&['-']) {
+                    TokenType::MinusMinus
+                } else {
+                    TokenType::Minus
+                };
+                self.add_token(token_type);
+            }
+            '+' => {
+                let token_type = if self.check_and_consume(&['=']) {
+                    TokenType::PlusEqual
+                } else if self.check_and_consume(&['+']) {
+                    TokenType::PlusPlus
+                } else {
+                    TokenType::Plus
+                };
+                self.add_token(token_type);
+            }
+            '*' => {
+                let token_type = if self.check_and_consume(&['=']) {
+                    TokenType::StarEqual
+                } else if self.check_and_consume(&['*']) {
+                    TokenType::StarStar
+                } else {
+                    TokenType::Star
+                };
+                self.add_token(token_type);
+            }
             '!' => {
                 let token_type = if self.check_and_consume(&['=']) {
                     TokenType::BangEqual
@@ -90,6 +163,10 @@ impl Scanner {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.consume();
                     }
+                } else if self.check_and_consume(&['*']) {
+                    self.parse_block_comment();
+                } else if self.check_and_consume(&['=']) {
+                    self.add_token(TokenType::SlashEqual);
                 } else {
                     self.add_token(TokenType::Slash);
                 }
@@ -106,66 +183,237 @@ impl Scanner {
             c if c.is_ascii_digit() => self.parse_number(),
 
             // Identifier (variable name/keywords)
-            c if Self::is_valid_identifier_char(c) => self.parse_identifier(),
+            c if Self::is_identifier_start(c) => self.parse_identifier(),
 
-            // TODO: Dot?
+            // A fraction without a leading zero, such as `.5`, unless the dot could be accessing a
+            // property of what came before it
+            '.' if self.peek().is_ascii_digit() && !self.can_access_property() => {
+                self.parse_number()
+            }
             '.' => self.add_token(TokenType::Dot),
 
-            _ => lox_generic_error(self.line, &format!("Unexpected character '{c}'")),
+            _ => self.error(&format!("Unexpected character '{c}'")),
         };
     }
 
+    /// Report an error at the most recently consumed character
+    fn error(&mut self, message: &str) {
+        let column = self.column.saturating_sub(1).max(1);
+        self.diagnostics
+            .push(Diagnostic::scan(self.line, column, message));
+    }
+
     fn add_token(&mut self, token_type: TokenType) {
         self.add_token_with_value(token_type, Literal::None);
     }
 
     fn add_token_with_value(&mut self, token_type: TokenType, literal: Literal) {
-        let text = self.source[self.start..self.current].to_string();
-        self.tokens
-            .push(Token::new(token_type, text, literal, self.line));
+        let text = self.source_slice(self.start, self.current);
+        self.pending = Some(Token::new(
+            token_type,
+            text,
+            literal,
+            self.start_line,
+            self.start_column,
+            self.start,
+        ));
     }
 
-    fn parse_string(&mut self) {
-        // Consume until we reach the end of the string or the input
-        while self.peek() != '"' && !self.is_at_end() {
+    fn parse_block_comment(&mut self) {
+        // Consume until we reach the closing `*/` or the end of the input
+        while !(self.peek() == '*' && self.peek_next() == '/') {
+            if self.is_at_end() {
+                self.error("Unterminated block comment.");
+                return;
+            }
             if self.peek() == '\n' {
                 self.line += 1;
             }
             self.consume();
         }
 
+        // Consume closing `*/`
+        self.consume();
+        self.consume();
+    }
+
+    fn parse_string(&mut self) {
+        let mut string = String::new();
+
+        // Consume until we reach the end of the string or the input
+        while self.peek() != '"' && !self.is_at_end() {
+            match self.consume() {
+                '\n' => {
+                    self.line += 1;
+                    string.push('\n');
+                }
+                '\\' => {
+                    if let Some(escaped) = self.parse_escape_sequence() {
+                        string.push(escaped);
+                    }
+                }
+                c => string.push(c),
+            }
+        }
+
         // If we hit this, it means we have an unclosed quote
         if self.is_at_end() {
-            lox_generic_error(self.line, "Unterminated string.");
+            self.diagnostics.push(Diagnostic::scan(
+                self.start_line,
+                self.start_column,
+                "Unterminated string.",
+            ));
+            return;
         }
 
         // Consume closing quote
         self.consume();
 
-        // Trim the quotes off
-        let string = self.source[self.start + 1..self.current - 1].to_string();
         self.add_token_with_value(TokenType::String, Literal::String(string));
     }
 
+    /// Decode the character following a backslash inside a string literal, reporting an error
+    /// and returning `None` if it is not a recognised escape sequence.
+    fn parse_escape_sequence(&mut self) -> Option<char> {
+        // An unterminated string will be reported by the caller
+        if self.is_at_end() {
+            return None;
+        }
+
+        match self.consume() {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '0' => Some('\0'),
+            'u' => self.parse_unicode_escape(),
+            c => {
+                if c == '\n' {
+                    self.line += 1;
+                }
+                self.error(&format!("Unknown escape sequence '\\{c}'."));
+                None
+            }
+        }
+    }
+
+    /// Decode a `\u{...}` escape, where the braces contain the hexadecimal code point of a
+    /// unicode scalar value.
+    fn parse_unicode_escape(&mut self) -> Option<char> {
+        if !self.check_and_consume(&['{']) {
+            self.error("Expected '{' after '\\u' in unicode escape.");
+            return None;
+        }
+
+        let mut digits = String::new();
+        while !matches!(self.peek(), '}' | '"' | '\n') && !self.is_at_end() {
+            digits.push(self.consume());
+        }
+
+        if !self.check_and_consume(&['}']) {
+            self.error("Expected '}' to close unicode escape.");
+            return None;
+        }
+
+        if digits.is_empty() {
+            self.error("Unicode escape '\\u{}' must contain a code point.");
+            return None;
+        }
+
+        if digits.len() > 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            self.error(&format!(
+                "Invalid hexadecimal code point in unicode escape '\\u{{{digits}}}'."
+            ));
+            return None;
+        }
+
+        // Surrogates and values above the unicode range are not valid scalar values
+        let code_point = u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32);
+        if code_point.is_none() {
+            self.error(&format!(
+                "Unicode escape '\\u{{{digits}}}' is not a valid unicode scalar value."
+            ));
+        }
+        code_point
+    }
+
     fn parse_number(&mut self) {
+        // A leading `0x` introduces a hexadecimal integer
+        if self.get_nth_char(self.start) == '0' && matches!(self.peek(), 'x' | 'X') {
+            self.parse_hex_number();
+            return;
+        }
+
         while self.peek().is_ascii_digit() {
             self.consume();
         }
 
-        // Look for a fractional part
-        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
+        // Look for a fractional part, unless the number began with one
+        let has_fraction = self.get_nth_char(self.start) == '.';
+        if !has_fraction && self.peek() == '.' && self.peek_next().is_ascii_digit() {
             // Consume the period
             self.consume();
 
             while self.peek().is_ascii_digit() {
                 self.consume();
             }
+        } else if !has_fraction
+            && self.peek() == '.'
+            && !Self::is_identifier_start(self.peek_next())
+        {
+            // A dot before a name, as in `3.foo`, is left to be a property access
+            self.consume();
+            self.error("Expected digit after decimal point.");
+            return;
         }
 
-        let number_slice = self.source[self.start..self.current].to_string();
-        let number = number_slice
-            .parse::<f64>()
-            .unwrap_or_else(|e| panic!("Failed to parse {} as a number: {}", number_slice, e));
+        // Look for an exponent part
+        if matches!(self.peek(), 'e' | 'E') {
+            // Consume the exponent marker and its optional sign
+            self.consume();
+            if matches!(self.peek(), '+' | '-') {
+                self.consume();
+            }
+
+            if !self.peek().is_ascii_digit() {
+                self.error("Expected digits in number exponent.");
+                return;
+            }
+
+            while self.peek().is_ascii_digit() {
+                self.consume();
+            }
+        }
+
+        let number_slice = self.source_slice(self.start, self.current);
+        match number_slice.parse::<f64>() {
+            Ok(number) => self.add_token_with_value(TokenType::Number, Literal::Number(number)),
+            Err(_) => self.error(&format!("Invalid number '{number_slice}'.")),
+        }
+    }
+
+    fn parse_hex_number(&mut self) {
+        // Consume the `x`
+        self.consume();
+
+        while self.peek().is_ascii_hexdigit() {
+            self.consume();
+        }
+
+        // Skip the `0x` prefix
+        let digits = self.source_slice(self.start + 2, self.current);
+        if digits.is_empty() {
+            self.error("Expected hexadecimal digits after '0x'.");
+            return;
+        }
+
+        let number = digits
+            .chars()
+            .filter_map(|c| c.to_digit(16))
+            .fold(0.0, |acc, digit| acc * 16.0 + digit as f64);
         self.add_token_with_value(TokenType::Number, Literal::Number(number));
     }
 
@@ -174,7 +422,7 @@ impl Scanner {
             self.consume();
         }
 
-        let identifier = self.source[self.start..self.current].to_string();
+        let identifier = self.source_slice(self.start, self.current);
         let identifier_token_type = KEYWORDS.get(&identifier).unwrap_or(&TokenType::Identifier);
 
         match identifier_token_type {
@@ -186,19 +434,79 @@ impl Scanner {
         }
     }
 
+    /// Whether a `.` at this point would follow something with properties, as in `point.x`
+    fn can_access_property(&self) -> bool {
+        matches!(
+            self.previous_token_type,
+            Some(
+                TokenType::Identifier
+                    | TokenType::This
+                    | TokenType::Super
+                    | TokenType::RightParen
+                    | TokenType::RightBracket
+            )
+        )
+    }
+
     fn get_current_char(&self) -> char {
         self.get_nth_char(self.current)
     }
 
     fn get_nth_char(&self, n: usize) -> char {
-        self.source
-            .chars()
-            .nth(n)
-            .unwrap_or_else(|| panic!("Failed to get character at index {}", self.current))
+        *self
+            .source
+            .get(n)
+            .unwrap_or_else(|| panic!("Failed to get character at index {}", n))
+    }
+
+    /// Build a string from the characters in the range `[start, end)`
+    fn source_slice(&self, start: usize, end: usize) -> String {
+        self.source[start..end].iter().collect()
+    }
+
+    /// Identifiers may use letters from any script, such as `café` or `π`, though keywords are
+    /// all ASCII
+    fn is_identifier_start(c: char) -> bool {
+        c.is_alphabetic() || c == '_'
     }
 
     fn is_valid_identifier_char(c: char) -> bool {
-        c.is_ascii_alphanumeric() || c == '_'
+        c.is_alphanumeric() || c == '_'
+    }
+}
+
+/// Tokens are scanned lazily, one at a time, ending with a single `Eof` token. Errors are
+/// collected along the way, so characters that don't form a token are skipped.
+impl Iterator for Scanner {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        while !self.is_at_end() {
+            self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column;
+            self.scan_token();
+
+            // Whitespace and comments don't produce a token, so keep scanning
+            if let Some(token) = self.pending.take() {
+                self.previous_token_type = Some(token.token_type);
+                return Some(token);
+            }
+        }
+
+        if self.finished {
+            return None;
+        }
+
+        self.finished = true;
+        Some(Token::new(
+            TokenType::Eof,
+            "".to_string(),
+            Literal::None,
+            self.line,
+            self.column,
+            self.current,
+        ))
     }
 }
 
@@ -210,6 +518,13 @@ impl GenericScanner<char> for Scanner {
     fn consume(&mut self) -> char {
         let curr_char = self.get_current_char();
         self.current += 1;
+        if curr_char == '\n' {
+            self.column = 1;
+        } else if curr_char == '\t' {
+            self.column += self.tab_width - (self.column - 1) % self.tab_width;
+        } else {
+            self.column += 1;
+        }
         curr_char
     }
 
@@ -221,7 +536,7 @@ impl GenericScanner<char> for Scanner {
         {
             false
         } else {
-            self.current += 1;
+            self.consume();
             true
         }
     }
@@ -265,3 +580,247 @@ lazy_static! {
         map
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Scanner;
+    use crate::test_util::output_of;
+    use crate::token::{Literal, Token, TokenType};
+
+    fn scan(source: &str) -> Vec<Token> {
+        match Scanner::new(source.to_string()).scan_tokens() {
+            Ok(tokens) => tokens,
+            Err(diagnostics) => panic!("Failed to scan: {:?}", diagnostics),
+        }
+    }
+
+    #[test]
+    fn scans_multi_byte_characters_by_char() {
+        let tokens = Scanner::new("var s = \"🎉 café\"; // ñ\nprint s;".to_string())
+            .scan_tokens()
+            .expect("source should scan");
+
+        let positions = tokens
+            .iter()
+            .map(|token| (token.lexeme.as_str(), token.line, token.column))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            [
+                ("var", 1, 1),
+                ("s", 1, 5),
+                ("=", 1, 7),
+                ("\"🎉 café\"", 1, 9),
+                (";", 1, 17),
+                ("print", 2, 1),
+                ("s", 2, 7),
+                (";", 2, 8),
+                ("", 2, 9),
+            ]
+        );
+        assert_eq!(tokens[3].token_type, TokenType::String);
+        assert_eq!(tokens[3].literal, Literal::String("🎉 café".to_string()));
+    }
+
+    #[test]
+    fn yields_eof_once_as_the_last_token() {
+        let mut scanner = Scanner::new("a + 1".to_string());
+        let types = scanner
+            .by_ref()
+            .map(|token| token.token_type)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            [
+                TokenType::Identifier,
+                TokenType::Plus,
+                TokenType::Number,
+                TokenType::Eof
+            ]
+        );
+        assert!(scanner.next().is_none());
+    }
+
+    #[test]
+    fn reports_unterminated_strings_where_they_start() {
+        let diagnostics =
+            match Scanner::new("print 1;\nvar s = \"abc\ndef\n\n".to_string()).scan_tokens() {
+                Ok(_) => panic!("Scanned an unterminated string"),
+                Err(diagnostics) => diagnostics,
+            };
+        let positions = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.line,
+                    diagnostic.column,
+                    diagnostic.message.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(positions, [(2, Some(9), "Unterminated string.")]);
+    }
+
+    #[test]
+    fn advances_tabs_to_the_next_multiple_of_the_tab_width() {
+        let columns = |tab_width| {
+            let mut scanner = Scanner::new("\t\tx\n\ta\tb".to_string());
+            scanner.set_tab_width(tab_width);
+            scanner
+                .scan_tokens()
+                .unwrap_or_default()
+                .iter()
+                .map(|token| (token.lexeme.clone(), token.line, token.column))
+                .filter(|(lexeme, _, _)| !lexeme.is_empty())
+                .collect::<Vec<_>>()
+        };
+        let token = |lexeme: &str, line, column| (lexeme.to_string(), line, column);
+
+        assert_eq!(
+            columns(1),
+            [token("x", 1, 3), token("a", 2, 2), token("b", 2, 4)]
+        );
+        assert_eq!(
+            columns(4),
+            [token("x", 1, 9), token("a", 2, 5), token("b", 2, 9)]
+        );
+    }
+
+    #[test]
+    fn scans_punctuation_for_ternaries_lists_and_remainders() {
+        let types = |source: &str| {
+            scan(source)
+                .iter()
+                .map(|token| token.token_type)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            types("a ? b : c"),
+            [
+                TokenType::Identifier,
+                TokenType::Question,
+                TokenType::Identifier,
+                TokenType::Colon,
+                TokenType::Identifier,
+                TokenType::Eof
+            ]
+        );
+        assert_eq!(
+            types("x[0] % 2"),
+            [
+                TokenType::Identifier,
+                TokenType::LeftBracket,
+                TokenType::Number,
+                TokenType::RightBracket,
+                TokenType::Percent,
+                TokenType::Number,
+                TokenType::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn scans_the_same_tokens_each_time() {
+        let mut scanner = Scanner::new("var a = 1;\nprint a;".to_string());
+        let first = scanner.scan_tokens().unwrap_or_default();
+        assert_eq!(first.len(), 9);
+
+        let second = scanner.scan_tokens().unwrap_or_default();
+        assert_eq!(second, first);
+
+        // Including after some tokens were taken by iterating
+        scanner.next();
+        assert_eq!(scanner.scan_tokens().unwrap_or_default(), first);
+    }
+
+    #[test]
+    fn scans_a_leading_dot_as_part_of_a_number() {
+        let tokens = scan(".5 obj.5");
+        let scanned = tokens
+            .iter()
+            .map(|token| (token.token_type, token.literal.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            scanned,
+            [
+                (TokenType::Number, Literal::Number(0.5)),
+                (TokenType::Identifier, Literal::None),
+                (TokenType::Dot, Literal::None),
+                (TokenType::Number, Literal::Number(5.0)),
+                (TokenType::Eof, Literal::None)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_a_trailing_decimal_point() {
+        let diagnostics = match Scanner::new("print 3.;".to_string()).scan_tokens() {
+            Ok(_) => panic!("Scanned a number without digits after its decimal point"),
+            Err(diagnostics) => diagnostics,
+        };
+        let positions = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.column, diagnostic.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            [(Some(8), "Expected digit after decimal point.")]
+        );
+    }
+
+    #[test]
+    fn scans_a_dot_followed_by_a_name_as_property_access() {
+        let types = scan("3.foo")
+            .iter()
+            .map(|token| token.token_type)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            [
+                TokenType::Number,
+                TokenType::Dot,
+                TokenType::Identifier,
+                TokenType::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_newlines_in_multi_line_strings() {
+        let tokens = scan("print \"one\ntwo\";\nx");
+        let scanned = tokens
+            .iter()
+            .map(|token| (token.token_type, token.literal.clone(), token.line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            scanned,
+            [
+                (TokenType::Print, Literal::None, 1),
+                (
+                    TokenType::String,
+                    Literal::String("one\ntwo".to_string()),
+                    1
+                ),
+                (TokenType::Semicolon, Literal::None, 2),
+                (TokenType::Identifier, Literal::None, 3),
+                (TokenType::Eof, Literal::None, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn declares_variables_with_unicode_names() {
+        let source = r#"var λόγος = "word"; var naïve_2 = 2; print λόγος, naïve_2 + 1;"#;
+        assert_eq!(output_of(source), "word 3\n");
+
+        let identifier = &scan("var ñu = 1;")[1];
+        assert_eq!(
+            (
+                identifier.token_type,
+                identifier.lexeme.as_str(),
+                identifier.span().end
+            ),
+            (TokenType::Identifier, "ñu", 6)
+        );
+    }
+}
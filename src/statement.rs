@@ -0,0 +1,125 @@
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::expression::Expression;
+use crate::token::Token;
+use crate::util::format_statement;
+
+#[derive(Clone)]
+pub enum Statement {
+    Block(Vec<Statement>),
+    Class {
+        name: Token,
+        /// Always a variable expression naming the class being inherited from
+        superclass: Option<Expression>,
+        methods: Vec<Rc<FunctionDeclaration>>,
+    },
+    Expression(Expression),
+    /// Shared with each function created by running the declaration, rather than copied
+    Function(Rc<FunctionDeclaration>),
+    If {
+        condition: Expression,
+        then_branch: Box<Statement>,
+        else_branch: Option<Box<Statement>>,
+    },
+    /// Values to print on one line, separated by spaces
+    Print {
+        keyword: Token,
+        expressions: Vec<Expression>,
+    },
+    Return {
+        keyword: Token,
+        value: Option<Expression>,
+    },
+    Var {
+        name: Token,
+        initializer: Option<Expression>,
+    },
+    While {
+        /// The `while` or `for` keyword, used to locate runtime errors
+        keyword: Token,
+        condition: Expression,
+        body: Box<Statement>,
+    },
+}
+
+/// A function, either declared on its own with `fun`, as a method of a class, or anonymously as
+/// an expression
+#[derive(Clone)]
+pub struct FunctionDeclaration {
+    /// Name of the function, or `None` for an anonymous function
+    pub name: Option<Token>,
+    pub params: Vec<Token>,
+    pub body: Vec<Statement>,
+}
+
+impl FunctionDeclaration {
+    /// Name of a function declared by a statement or as a method, which always has one
+    pub fn declared_name(&self) -> &Token {
+        self.name
+            .as_ref()
+            .expect("Only anonymous functions are unnamed")
+    }
+}
+
+/// Operation over statements, with a method for each kind of statement, in the same way as
+/// [`crate::expression::Visitor`] is for expressions
+pub trait Visitor<T> {
+    fn visit_block(&mut self, statements: &[Statement]) -> T;
+    fn visit_class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expression>,
+        methods: &[Rc<FunctionDeclaration>],
+    ) -> T;
+    fn visit_expression(&mut self, expression: &Expression) -> T;
+    fn visit_function(&mut self, declaration: &Rc<FunctionDeclaration>) -> T;
+    fn visit_if(
+        &mut self,
+        condition: &Expression,
+        then_branch: &Statement,
+        else_branch: Option<&Statement>,
+    ) -> T;
+    fn visit_print(&mut self, keyword: &Token, expressions: &[Expression]) -> T;
+    fn visit_return(&mut self, keyword: &Token, value: Option<&Expression>) -> T;
+    fn visit_var(&mut self, name: &Token, initializer: Option<&Expression>) -> T;
+    fn visit_while(&mut self, keyword: &Token, condition: &Expression, body: &Statement) -> T;
+}
+
+impl Statement {
+    /// Call the visitor method matching this statement's variant
+    pub fn accept<T, V: Visitor<T> + ?Sized>(&self, visitor: &mut V) -> T {
+        match self {
+            Statement::Block(statements) => visitor.visit_block(statements),
+            Statement::Class {
+                name,
+                superclass,
+                methods,
+            } => visitor.visit_class(name, superclass.as_ref(), methods),
+            Statement::Expression(expression) => visitor.visit_expression(expression),
+            Statement::Function(declaration) => visitor.visit_function(declaration),
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => visitor.visit_if(condition, then_branch, else_branch.as_deref()),
+            Statement::Print {
+                keyword,
+                expressions,
+            } => visitor.visit_print(keyword, expressions),
+            Statement::Return { keyword, value } => visitor.visit_return(keyword, value.as_ref()),
+            Statement::Var { name, initializer } => visitor.visit_var(name, initializer.as_ref()),
+            Statement::While {
+                keyword,
+                condition,
+                body,
+            } => visitor.visit_while(keyword, condition, body),
+        }
+    }
+}
+
+impl Display for Statement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format_statement(self))
+    }
+}
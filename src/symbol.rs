@@ -0,0 +1,79 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+/// An interned string, such as a variable name. Symbols for the same text are equal, so they can
+/// be compared and hashed as integers rather than strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// Get the symbol for the given text, interning it if it hasn't been seen before
+    pub fn intern(text: &str) -> Self {
+        INTERNER.with(|interner| interner.borrow_mut().intern(text))
+    }
+
+    /// Look up the text this symbol was interned from
+    pub fn as_string(&self) -> String {
+        INTERNER.with(|interner| interner.borrow().strings[self.0 as usize].clone())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_string())
+    }
+}
+
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<String, u32>,
+    /// Text of each symbol, indexed by its id
+    strings: Vec<String>,
+}
+
+impl Interner {
+    fn intern(&mut self, text: &str) -> Symbol {
+        if let Some(&id) = self.symbols.get(text) {
+            return Symbol(id);
+        }
+
+        let id = u32::try_from(self.strings.len()).expect("Ran out of symbols to intern");
+        self.symbols.insert(text.to_string(), id);
+        self.strings.push(text.to_string());
+        Symbol(id)
+    }
+}
+
+thread_local! {
+    /// Symbols are shared by everything on a thread, so that tokens from separate scans of REPL
+    /// input agree with each other and with names defined by the interpreter itself
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Symbol;
+    use crate::test_util::output_of;
+
+    #[test]
+    fn interns_equal_text_as_one_symbol() {
+        let count = Symbol::intern("count");
+        assert_eq!(Symbol::intern("count"), count);
+        assert_ne!(Symbol::intern("counter"), count);
+        assert_eq!(count.as_string(), "count");
+    }
+
+    #[test]
+    fn looks_up_variables_in_a_loop_heavy_program() {
+        let source = "
+            var total = 0;
+            for (var i = 0; i < 10000; i = i + 1) {
+                var square = i * i;
+                total = total + (square % 2 == 0 ? 1 : 0);
+            }
+            print total;
+        ";
+        assert_eq!(output_of(source), "5000\n");
+    }
+}
@@ -0,0 +1,67 @@
+//! Helpers shared by the unit tests of several modules
+
+use std::thread;
+
+use crate::error::{Diagnostic, Severity};
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::{run_source, Interpreter, Value};
+
+pub mod output;
+
+pub use output::capturing_interpreter;
+
+/// Scan, parse, resolve and run a program with the given interpreter
+pub fn run_with(interpreter: &mut Interpreter, source: &str) -> Result<(), Vec<Diagnostic>> {
+    let tokens = Scanner::new(source.to_string()).scan_tokens()?;
+    let statements = Parser::new(tokens).parse()?;
+    Resolver::new(interpreter).resolve(&statements)?;
+    interpreter
+        .interpret(statements)
+        .map(|_| ())
+        .map_err(|error| vec![Diagnostic::from(error)])
+}
+
+/// Run a program with a fresh interpreter and return what it printed, failing the test if the
+/// program reports an error
+pub fn output_of(source: &str) -> String {
+    let (mut interpreter, output) = capturing_interpreter();
+    if let Err(diagnostics) = run_with(&mut interpreter, source) {
+        panic!("Program failed: {:?}", diagnostics);
+    }
+    output.contents()
+}
+
+/// Run a program with a fresh interpreter and return the value of each top-level expression
+/// statement, failing the test if the program reports an error
+pub fn values_of(source: &str) -> Vec<Value> {
+    match run_source(source) {
+        Ok(output) => output.values,
+        Err(diagnostics) => panic!("Program failed: {:?}", diagnostics),
+    }
+}
+
+/// Run a program with a fresh interpreter and return the messages of the errors it reports,
+/// failing the test if it succeeds
+pub fn errors_of(source: &str) -> Vec<String> {
+    match run_source(source) {
+        Ok(_) => panic!("Program succeeded but was expected to fail"),
+        Err(diagnostics) => diagnostics
+            .into_iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(|diagnostic| diagnostic.message)
+            .collect(),
+    }
+}
+
+/// Run a test on a thread with as large a stack as the lox binary gives the interpreter, for
+/// programs which recurse too deeply for the default stack in unoptimised builds
+pub fn on_large_stack<T: Send + 'static>(test: impl FnOnce() -> T + Send + 'static) -> T {
+    thread::Builder::new()
+        .stack_size(256 * 1024 * 1024)
+        .spawn(test)
+        .expect("Failed to spawn test thread")
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
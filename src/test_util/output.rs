@@ -0,0 +1,36 @@
+//! Capturing what a program prints, shared with the tests of the lox binary, so this only uses
+//! the crate's public API
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::Interpreter;
+
+/// Program output which a test can read back after handing the writer to an interpreter
+#[derive(Clone, Default)]
+pub struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl SharedOutput {
+    pub fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).expect("Program output was not UTF-8")
+    }
+}
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An interpreter whose output is captured rather than written to stdout
+pub fn capturing_interpreter() -> (Interpreter, SharedOutput) {
+    let output = SharedOutput::default();
+    let interpreter = Interpreter::with_output(Box::new(output.clone()));
+    (interpreter, output)
+}
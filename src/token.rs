@@ -1,11 +1,69 @@
+use std::cell::Cell;
 use std::fmt;
 
+use crate::symbol::Symbol;
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
+    /// The lexeme of an identifier, `this` or `super`, interned so that names can be compared
+    /// cheaply. Other tokens are never looked up by name, so they are not interned.
+    pub symbol: Option<Symbol>,
     pub literal: Literal,
     pub line: usize,
+    pub column: usize,
+    /// Number of characters before the token in the source
+    pub offset: usize,
+    /// Tells this token apart from every other token created on the thread, including ones at
+    /// the same position in another line of REPL input
+    pub id: TokenId,
+}
+
+/// Identity of a single token, see [`Token::id`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TokenId(u64);
+
+impl TokenId {
+    fn next() -> Self {
+        NEXT_TOKEN_ID.with(|next| {
+            let id = next.get();
+            next.set(id + 1);
+            TokenId(id)
+        })
+    }
+}
+
+thread_local! {
+    static NEXT_TOKEN_ID: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Range of characters in the source covered by a token or expression, from `start` up to but
+/// not including `end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    /// Line and column of the character at `start`, for reporting the span's position
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// The smallest span covering both this span and another
+    pub fn to(self, other: Span) -> Span {
+        let first = if self.start <= other.start {
+            self
+        } else {
+            other
+        };
+        Span {
+            start: first.start,
+            end: self.end.max(other.end),
+            line: first.line,
+            column: first.column,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -17,16 +75,58 @@ pub enum Literal {
 }
 
 impl Token {
-    pub fn new(r#type: TokenType, lexeme: String, literal: Literal, line: usize) -> Self {
+    pub fn new(
+        r#type: TokenType,
+        lexeme: String,
+        literal: Literal,
+        line: usize,
+        column: usize,
+        offset: usize,
+    ) -> Self {
         Token {
             token_type: r#type,
+            symbol: matches!(
+                r#type,
+                TokenType::Identifier | TokenType::This | TokenType::Super
+            )
+            .then(|| Symbol::intern(&lexeme)),
             lexeme,
             literal,
             line,
+            column,
+            offset,
+            id: TokenId::next(),
+        }
+    }
+
+    /// The interned name of an identifier, `this` or `super` token
+    pub fn symbol(&self) -> Symbol {
+        self.symbol.expect("Only names are interned")
+    }
+
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.offset,
+            end: self.offset + self.lexeme.chars().count(),
+            line: self.line,
+            column: self.column,
         }
     }
 }
 
+/// Tokens are compared by their type, text and position in the source, ignoring the literal and
+/// id, so that scanning the same source twice gives equal tokens
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.token_type == other.token_type
+            && self.lexeme == other.lexeme
+            && self.line == other.line
+            && self.column == other.column
+    }
+}
+
+impl Eq for Token {}
+
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -37,13 +137,15 @@ impl fmt::Display for Token {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TokenType {
     // Single-character tokens
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -51,6 +153,9 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Percent,
+    Question,
+    Colon,
 
     // One or two character tokens
     Bang,
@@ -61,6 +166,13 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PlusPlus,
+    MinusMinus,
+    StarStar,
 
     // Literals
     Identifier,
@@ -88,8 +200,87 @@ pub enum TokenType {
     Eof,
 }
 
+impl TokenType {
+    /// Whether this is a reserved word, which can't be used as a name
+    pub fn is_keyword(self) -> bool {
+        matches!(
+            self,
+            TokenType::And
+                | TokenType::Class
+                | TokenType::Else
+                | TokenType::False
+                | TokenType::Fun
+                | TokenType::For
+                | TokenType::If
+                | TokenType::Nil
+                | TokenType::Or
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Super
+                | TokenType::This
+                | TokenType::True
+                | TokenType::Var
+                | TokenType::While
+        )
+    }
+}
+
 impl PartialEq<Token> for TokenType {
     fn eq(&self, other: &Token) -> bool {
         &other.token_type == self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Literal, Span, Token, TokenType};
+    use crate::symbol::Symbol;
+
+    #[test]
+    fn keeps_the_fields_it_was_created_with() {
+        let token = Token::new(
+            TokenType::Number,
+            "12.5".to_string(),
+            Literal::Number(12.5),
+            3,
+            7,
+            40,
+        );
+
+        assert_eq!(token.token_type, TokenType::Number);
+        assert_eq!(token.lexeme, "12.5");
+        assert_eq!(token.literal, Literal::Number(12.5));
+        assert_eq!((token.line, token.column, token.offset), (3, 7, 40));
+        assert_eq!(
+            token.span(),
+            Span {
+                start: 40,
+                end: 44,
+                line: 3,
+                column: 7
+            }
+        );
+    }
+
+    #[test]
+    fn interns_only_names() {
+        let token = |token_type, lexeme: &str| {
+            Token::new(token_type, lexeme.to_string(), Literal::None, 1, 1, 0)
+        };
+
+        assert_eq!(
+            token(TokenType::Identifier, "count").symbol,
+            Some(Symbol::intern("count"))
+        );
+        assert_eq!(
+            token(TokenType::This, "this").symbol(),
+            Symbol::intern("this")
+        );
+        assert_eq!(
+            token(TokenType::Super, "super").symbol(),
+            Symbol::intern("super")
+        );
+        assert_eq!(token(TokenType::Plus, "+").symbol, None);
+        assert_eq!(token(TokenType::String, "\"count\"").symbol, None);
+    }
+}
@@ -1,42 +1,542 @@
-use crate::expression::Expression;
-use crate::token::Literal;
+use crate::expression::{Expression, Visitor};
+use crate::statement::{FunctionDeclaration, Statement};
+use crate::token::{Literal, Token};
 
 pub trait AstPrinter {
+    /// Format an expression in parenthesised prefix notation, e.g. `(* (group (+ 1 2)) 3)`
     fn format_ast(expression: &Expression) -> String;
+
+    /// Format an expression in reverse Polish notation, with each operator following its
+    /// operands, e.g. `1 2 + 3 *`. Grouping is implied by the ordering, so groups are omitted.
+    fn format_rpn(expression: &Expression) -> String;
+
+    /// Format an expression as a Graphviz `digraph`, with a node per expression and an edge
+    /// from each expression to its operands. The output can be rendered with `dot -Tpng`.
+    fn to_dot(expression: &Expression) -> String;
 }
 
 impl AstPrinter for Expression {
     fn format_ast(expression: &Expression) -> String {
-        match expression {
-            Expression::Binary {
-                left,
-                operator,
-                right,
-            } => parenthesise(&operator.lexeme, &[*left.clone(), *right.clone()]),
-            Expression::Grouping { expression } => parenthesise("group", &[*expression.clone()]),
-            Expression::Literal { value } => match value {
-                Literal::String(str) => str.to_string(),
-                Literal::Number(num) => num.to_string(),
-                Literal::Boolean(bool) => bool.to_string(),
-                Literal::None => "nil".to_string(),
-            },
-            Expression::Unary { operator, right } => {
-                parenthesise(&operator.lexeme, &[*right.clone()])
-            }
-        }
-    }
-}
-
-fn parenthesise(name: &str, expressions: &[Expression]) -> String {
+        expression.accept(&mut PrefixPrinter)
+    }
+
+    fn format_rpn(expression: &Expression) -> String {
+        expression.accept(&mut RpnPrinter)
+    }
+
+    fn to_dot(expression: &Expression) -> String {
+        let mut graph = DotGraph::default();
+        expression.accept(&mut graph);
+
+        let mut builder = String::from("digraph ast {\n");
+        for line in graph.lines {
+            builder.push_str("    ");
+            builder.push_str(&line);
+            builder.push('\n');
+        }
+        builder.push_str("}\n");
+        builder
+    }
+}
+
+/// Format a statement in the same parenthesised prefix notation as expressions, e.g.
+/// `(var x (+ 1 2))`, with expression statements written as `(; expression)`
+pub fn format_statement(statement: &Statement) -> String {
+    match statement {
+        Statement::Block(statements) => group("block", statements.iter().map(format_statement)),
+        Statement::Class {
+            name,
+            superclass,
+            methods,
+        } => {
+            let name = match superclass {
+                Some(superclass) => format!("class {} < {}", name.lexeme, superclass),
+                None => format!("class {}", name.lexeme),
+            };
+            group(&name, methods.iter().map(|method| format_function(method)))
+        }
+        Statement::Expression(expression) => format!("(; {})", expression),
+        Statement::Function(declaration) => format_function(declaration),
+        Statement::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => group(
+            &format!("if {}", condition),
+            std::iter::once(then_branch)
+                .chain(else_branch)
+                .map(|branch| format_statement(branch)),
+        ),
+        Statement::Print { expressions, .. } => {
+            group("print", expressions.iter().map(|e| e.to_string()))
+        }
+        Statement::Return { value, .. } => group("return", value.iter().map(|v| v.to_string())),
+        Statement::Var { name, initializer } => group(
+            &format!("var {}", name.lexeme),
+            initializer.iter().map(|v| v.to_string()),
+        ),
+        Statement::While {
+            condition, body, ..
+        } => {
+            format!("(while {} {})", condition, format_statement(body))
+        }
+    }
+}
+
+fn format_function(declaration: &FunctionDeclaration) -> String {
+    let params = declaration
+        .params
+        .iter()
+        .map(|param| param.lexeme.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let name = declaration
+        .name
+        .as_ref()
+        .map_or(String::new(), |name| format!(" {}", name.lexeme));
+    group(
+        &format!("fun{} ({})", name, params),
+        declaration.body.iter().map(format_statement),
+    )
+}
+
+/// Wrap already formatted parts in parentheses after the given name
+fn group(name: &str, parts: impl Iterator<Item = String>) -> String {
     let mut builder = String::new();
     builder.push('(');
     builder.push_str(name);
 
-    for expression in expressions {
+    for part in parts {
         builder.push(' ');
-        builder.push_str(&expression.to_string())
+        builder.push_str(&part);
     }
 
     builder.push(')');
     builder
 }
+
+struct PrefixPrinter;
+
+impl PrefixPrinter {
+    fn parenthesise(&mut self, name: &str, expressions: &[&Expression]) -> String {
+        let mut builder = String::new();
+        builder.push('(');
+        builder.push_str(name);
+
+        for expression in expressions {
+            builder.push(' ');
+            builder.push_str(&expression.accept(self))
+        }
+
+        builder.push(')');
+        builder
+    }
+}
+
+impl Visitor<String> for PrefixPrinter {
+    fn visit_assign(&mut self, name: &Token, value: &Expression) -> String {
+        self.parenthesise(&format!("= {}", name.lexeme), &[value])
+    }
+
+    fn visit_binary(&mut self, left: &Expression, operator: &Token, right: &Expression) -> String {
+        self.parenthesise(&operator.lexeme, &[left, right])
+    }
+
+    fn visit_call(&mut self, callee: &Expression, _: &Token, arguments: &[Expression]) -> String {
+        let mut expressions = vec![callee];
+        expressions.extend(arguments);
+        self.parenthesise("call", &expressions)
+    }
+
+    fn visit_comma(&mut self, expressions: &[Expression]) -> String {
+        self.parenthesise("comma", &expressions.iter().collect::<Vec<_>>())
+    }
+
+    fn visit_get(&mut self, object: &Expression, name: &Token) -> String {
+        self.parenthesise(&format!(". {}", name.lexeme), &[object])
+    }
+
+    fn visit_grouping(&mut self, expression: &Expression) -> String {
+        self.parenthesise("group", &[expression])
+    }
+
+    fn visit_index(&mut self, collection: &Expression, _: &Token, index: &Expression) -> String {
+        self.parenthesise("index", &[collection, index])
+    }
+
+    fn visit_index_set(
+        &mut self,
+        collection: &Expression,
+        _: &Token,
+        index: &Expression,
+        value: &Expression,
+    ) -> String {
+        self.parenthesise("index=", &[collection, index, value])
+    }
+
+    fn visit_lambda(&mut self, _: &Token, declaration: &FunctionDeclaration) -> String {
+        format_function(declaration)
+    }
+
+    fn visit_list_literal(&mut self, elements: &[Expression]) -> String {
+        self.parenthesise("list", &elements.iter().collect::<Vec<_>>())
+    }
+
+    fn visit_literal(&mut self, value: &Literal) -> String {
+        format_literal(value)
+    }
+
+    fn visit_logical(&mut self, left: &Expression, operator: &Token, right: &Expression) -> String {
+        self.parenthesise(&operator.lexeme, &[left, right])
+    }
+
+    fn visit_set(&mut self, object: &Expression, name: &Token, value: &Expression) -> String {
+        self.parenthesise(&format!("= .{}", name.lexeme), &[object, value])
+    }
+
+    fn visit_super(&mut self, _: &Token, method: &Token) -> String {
+        format!("(super {})", method.lexeme)
+    }
+
+    fn visit_ternary(
+        &mut self,
+        condition: &Expression,
+        then_expr: &Expression,
+        else_expr: &Expression,
+    ) -> String {
+        self.parenthesise("?:", &[condition, then_expr, else_expr])
+    }
+
+    fn visit_this(&mut self, _: &Token) -> String {
+        "this".to_string()
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expression) -> String {
+        self.parenthesise(&operator.lexeme, &[right])
+    }
+
+    fn visit_variable(&mut self, name: &Token) -> String {
+        name.lexeme.clone()
+    }
+}
+
+struct RpnPrinter;
+
+impl RpnPrinter {
+    fn postfix(&mut self, name: &str, expressions: &[&Expression]) -> String {
+        let mut builder = String::new();
+
+        for expression in expressions {
+            builder.push_str(&expression.accept(self));
+            builder.push(' ');
+        }
+
+        builder.push_str(name);
+        builder
+    }
+}
+
+impl Visitor<String> for RpnPrinter {
+    fn visit_assign(&mut self, name: &Token, value: &Expression) -> String {
+        self.postfix(&format!("{} =", name.lexeme), &[value])
+    }
+
+    fn visit_binary(&mut self, left: &Expression, operator: &Token, right: &Expression) -> String {
+        self.postfix(&operator.lexeme, &[left, right])
+    }
+
+    fn visit_call(&mut self, callee: &Expression, _: &Token, arguments: &[Expression]) -> String {
+        let mut expressions = vec![callee];
+        expressions.extend(arguments);
+        self.postfix("call", &expressions)
+    }
+
+    fn visit_comma(&mut self, expressions: &[Expression]) -> String {
+        self.postfix(",", &expressions.iter().collect::<Vec<_>>())
+    }
+
+    fn visit_get(&mut self, object: &Expression, name: &Token) -> String {
+        self.postfix(&format!("{} .", name.lexeme), &[object])
+    }
+
+    fn visit_grouping(&mut self, expression: &Expression) -> String {
+        expression.accept(self)
+    }
+
+    fn visit_index(&mut self, collection: &Expression, _: &Token, index: &Expression) -> String {
+        self.postfix("[]", &[collection, index])
+    }
+
+    fn visit_index_set(
+        &mut self,
+        collection: &Expression,
+        _: &Token,
+        index: &Expression,
+        value: &Expression,
+    ) -> String {
+        self.postfix("[]=", &[collection, index, value])
+    }
+
+    fn visit_lambda(&mut self, _: &Token, declaration: &FunctionDeclaration) -> String {
+        format_function(declaration)
+    }
+
+    fn visit_list_literal(&mut self, elements: &[Expression]) -> String {
+        self.postfix(
+            &format!("list{}", elements.len()),
+            &elements.iter().collect::<Vec<_>>(),
+        )
+    }
+
+    fn visit_literal(&mut self, value: &Literal) -> String {
+        format_literal(value)
+    }
+
+    fn visit_logical(&mut self, left: &Expression, operator: &Token, right: &Expression) -> String {
+        self.postfix(&operator.lexeme, &[left, right])
+    }
+
+    fn visit_set(&mut self, object: &Expression, name: &Token, value: &Expression) -> String {
+        self.postfix(&format!("{} .=", name.lexeme), &[object, value])
+    }
+
+    fn visit_super(&mut self, _: &Token, method: &Token) -> String {
+        format!("super.{}", method.lexeme)
+    }
+
+    fn visit_ternary(
+        &mut self,
+        condition: &Expression,
+        then_expr: &Expression,
+        else_expr: &Expression,
+    ) -> String {
+        self.postfix("?:", &[condition, then_expr, else_expr])
+    }
+
+    fn visit_this(&mut self, _: &Token) -> String {
+        "this".to_string()
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expression) -> String {
+        self.postfix(&operator.lexeme, &[right])
+    }
+
+    fn visit_variable(&mut self, name: &Token) -> String {
+        name.lexeme.clone()
+    }
+}
+
+/// Nodes and edges of a DOT graph, in the order they were visited. Visiting an expression adds
+/// a node for it and, recursively, its operands, returning the new node's id.
+#[derive(Default)]
+struct DotGraph {
+    next_id: usize,
+    lines: Vec<String>,
+}
+
+impl DotGraph {
+    fn node(&mut self, label: &str, children: &[&Expression]) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        // Backslashes and quotes would otherwise end the label early
+        let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+        self.lines.push(format!("node{id} [label=\"{label}\"];"));
+
+        for child in children {
+            let child_id = child.accept(self);
+            self.lines.push(format!("node{id} -> node{child_id};"));
+        }
+
+        id
+    }
+}
+
+impl Visitor<usize> for DotGraph {
+    fn visit_assign(&mut self, name: &Token, value: &Expression) -> usize {
+        self.node(&format!("Assign {}", name.lexeme), &[value])
+    }
+
+    fn visit_binary(&mut self, left: &Expression, operator: &Token, right: &Expression) -> usize {
+        self.node(&format!("Binary {}", operator.lexeme), &[left, right])
+    }
+
+    fn visit_call(&mut self, callee: &Expression, _: &Token, arguments: &[Expression]) -> usize {
+        let mut children = vec![callee];
+        children.extend(arguments);
+        self.node("Call", &children)
+    }
+
+    fn visit_comma(&mut self, expressions: &[Expression]) -> usize {
+        self.node("Comma", &expressions.iter().collect::<Vec<_>>())
+    }
+
+    fn visit_get(&mut self, object: &Expression, name: &Token) -> usize {
+        self.node(&format!("Get .{}", name.lexeme), &[object])
+    }
+
+    fn visit_grouping(&mut self, expression: &Expression) -> usize {
+        self.node("Grouping", &[expression])
+    }
+
+    fn visit_index(&mut self, collection: &Expression, _: &Token, index: &Expression) -> usize {
+        self.node("Index", &[collection, index])
+    }
+
+    fn visit_index_set(
+        &mut self,
+        collection: &Expression,
+        _: &Token,
+        index: &Expression,
+        value: &Expression,
+    ) -> usize {
+        self.node("IndexSet", &[collection, index, value])
+    }
+
+    fn visit_lambda(&mut self, _: &Token, declaration: &FunctionDeclaration) -> usize {
+        let params = declaration
+            .params
+            .iter()
+            .map(|param| param.lexeme.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.node(&format!("Lambda ({params})"), &[])
+    }
+
+    fn visit_list_literal(&mut self, elements: &[Expression]) -> usize {
+        self.node("ListLiteral", &elements.iter().collect::<Vec<_>>())
+    }
+
+    fn visit_literal(&mut self, value: &Literal) -> usize {
+        self.node(&format!("Literal {}", format_literal(value)), &[])
+    }
+
+    fn visit_logical(&mut self, left: &Expression, operator: &Token, right: &Expression) -> usize {
+        self.node(&format!("Logical {}", operator.lexeme), &[left, right])
+    }
+
+    fn visit_set(&mut self, object: &Expression, name: &Token, value: &Expression) -> usize {
+        self.node(&format!("Set .{}", name.lexeme), &[object, value])
+    }
+
+    fn visit_super(&mut self, _: &Token, method: &Token) -> usize {
+        self.node(&format!("Super .{}", method.lexeme), &[])
+    }
+
+    fn visit_ternary(
+        &mut self,
+        condition: &Expression,
+        then_expr: &Expression,
+        else_expr: &Expression,
+    ) -> usize {
+        self.node("Ternary", &[condition, then_expr, else_expr])
+    }
+
+    fn visit_this(&mut self, _: &Token) -> usize {
+        self.node("This", &[])
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expression) -> usize {
+        self.node(&format!("Unary {}", operator.lexeme), &[right])
+    }
+
+    fn visit_variable(&mut self, name: &Token) -> usize {
+        self.node(&format!("Variable {}", name.lexeme), &[])
+    }
+}
+
+fn format_literal(literal: &Literal) -> String {
+    match literal {
+        Literal::String(str) => str.to_string(),
+        Literal::Number(num) => num.to_string(),
+        Literal::Boolean(bool) => bool.to_string(),
+        Literal::None => "nil".to_string(),
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::AstPrinter;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::statement::Statement;
+    use crate::Expression;
+
+    /// Parse a single expression statement and return its expression
+    fn expression(source: &str) -> Expression {
+        let tokens = Scanner::new(format!("{source};"))
+            .scan_tokens()
+            .expect("source should scan");
+        match Parser::new(tokens).parse().ok().and_then(|mut s| s.pop()) {
+            Some(Statement::Expression(expression)) => expression,
+            _ => panic!("Expected an expression statement"),
+        }
+    }
+
+    #[test]
+    fn formats_rpn_with_operands_before_operators() {
+        let rpn = |source| Expression::format_rpn(&expression(source));
+        assert_eq!(rpn("(1 + 2) * (4 - 3)"), "1 2 + 4 3 - *");
+        assert_eq!(rpn("-(1 + 2) * 3"), "1 2 + - 3 *");
+        assert_eq!(rpn("!!true"), "true ! !");
+        assert_eq!(rpn("((1))"), "1");
+        assert_eq!(rpn("1 - (2 - (3 - 4))"), "1 2 3 4 - - -");
+    }
+
+    #[test]
+    fn graphs_each_expression_as_a_node_with_edges_to_its_operands() {
+        let dot = Expression::to_dot(&expression("-1 + 2 * x"));
+        assert!(dot.starts_with("digraph ast {\n") && dot.ends_with("}\n"));
+
+        let nodes = dot.lines().filter(|line| line.contains("[label=")).count();
+        let edges = dot.lines().filter(|line| line.contains(" -> ")).count();
+        assert_eq!((nodes, edges), (6, 5));
+        assert!(dot.contains("node0 [label=\"Binary +\"];"));
+        assert!(dot.contains("[label=\"Literal 2\"];"));
+    }
+
+    #[test]
+    fn formats_each_notation_of_one_expression() {
+        let expression = expression("-a * (b + 1)");
+        assert_eq!(
+            Expression::format_ast(&expression),
+            "(* (- a) (group (+ b 1)))"
+        );
+        assert_eq!(Expression::format_rpn(&expression), "a - b 1 + *");
+        assert_eq!(
+            Expression::to_dot(&expression),
+            concat!(
+                "digraph ast {\n",
+                "    node0 [label=\"Binary *\"];\n",
+                "    node1 [label=\"Unary -\"];\n",
+                "    node2 [label=\"Variable a\"];\n",
+                "    node1 -> node2;\n",
+                "    node0 -> node1;\n",
+                "    node3 [label=\"Grouping\"];\n",
+                "    node4 [label=\"Binary +\"];\n",
+                "    node5 [label=\"Variable b\"];\n",
+                "    node4 -> node5;\n",
+                "    node6 [label=\"Literal 1\"];\n",
+                "    node4 -> node6;\n",
+                "    node3 -> node4;\n",
+                "    node0 -> node3;\n",
+                "}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn formats_deeply_nested_expressions() {
+        let depth = 500;
+        let source = vec!["1"; depth + 1].join(" + ");
+        let expression = expression(&source);
+
+        assert_eq!(
+            Expression::format_ast(&expression),
+            format!("{}1{}", "(+ ".repeat(depth), " 1)".repeat(depth))
+        );
+        assert_eq!(
+            Expression::format_rpn(&expression),
+            format!("1{}", " 1 +".repeat(depth))
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! Serialisation of syntax trees to JSON, for consumption by external tooling.
+//!
+//! Every expression and statement becomes an object whose `"type"` is the name of its variant,
+//! e.g. `{"type":"Binary","operator":"+","left":...,"right":...}`. The remaining keys mirror the
+//! fields of the variant:
+//!
+//! - Tokens are serialised as their lexeme, so names and operators are plain strings.
+//! - Nested expressions and statements are objects, and lists of them are arrays.
+//! - Missing optional fields, such as a `var` without an initialiser, are `null`.
+//! - Literal values are the matching JSON string, number, boolean or `null`.
+//! - Unnamed contents are stored under `statements` for `Block`, `expression` for `Expression`
+//!   and `expressions` for `Print`. Functions, including methods, have `name`, `params` and `body`,
+//!   while anonymous `Lambda` functions have just `params` and `body`.
+//!
+//! Keys always appear in the order listed by the variant, so the output is stable.
+
+use std::rc::Rc;
+
+use crate::expression::Expression;
+use crate::statement::{FunctionDeclaration, Statement};
+use crate::token::{Literal, Token};
+
+pub trait ToJson {
+    fn to_json(&self) -> String;
+}
+
+/// Shared nodes, such as the declarations of methods, are written out as the node itself
+impl<T: ToJson> ToJson for Rc<T> {
+    fn to_json(&self) -> String {
+        T::to_json(self)
+    }
+}
+
+impl ToJson for Expression {
+    fn to_json(&self) -> String {
+        match self {
+            Expression::Assign { name, value } => object(
+                "Assign",
+                &[("name", token(name)), ("value", value.to_json())],
+            ),
+            Expression::Binary {
+                left,
+                operator,
+                right,
+            } => object(
+                "Binary",
+                &[
+                    ("operator", token(operator)),
+                    ("left", left.to_json()),
+                    ("right", right.to_json()),
+                ],
+            ),
+            Expression::Call {
+                callee, arguments, ..
+            } => object(
+                "Call",
+                &[
+                    ("callee", callee.to_json()),
+                    ("arguments", array(arguments)),
+                ],
+            ),
+            Expression::Comma { expressions } => {
+                object("Comma", &[("expressions", array(expressions))])
+            }
+            Expression::Get {
+                object: target,
+                name,
+            } => object(
+                "Get",
+                &[("object", target.to_json()), ("name", token(name))],
+            ),
+            Expression::Grouping { expression, .. } => {
+                object("Grouping", &[("expression", expression.to_json())])
+            }
+            Expression::Index {
+                collection, index, ..
+            } => object(
+                "Index",
+                &[
+                    ("collection", collection.to_json()),
+                    ("index", index.to_json()),
+                ],
+            ),
+            Expression::IndexSet {
+                collection,
+                index,
+                value,
+                ..
+            } => object(
+                "IndexSet",
+                &[
+                    ("collection", collection.to_json()),
+                    ("index", index.to_json()),
+                    ("value", value.to_json()),
+                ],
+            ),
+            Expression::Lambda { declaration, .. } => object(
+                "Lambda",
+                &[
+                    ("params", params(&declaration.params)),
+                    ("body", array(&declaration.body)),
+                ],
+            ),
+            Expression::ListLiteral { elements, .. } => {
+                object("ListLiteral", &[("elements", array(elements))])
+            }
+            Expression::Literal { value, .. } => object("Literal", &[("value", literal(value))]),
+            Expression::Logical {
+                left,
+                operator,
+                right,
+            } => object(
+                "Logical",
+                &[
+                    ("operator", token(operator)),
+                    ("left", left.to_json()),
+                    ("right", right.to_json()),
+                ],
+            ),
+            Expression::Set {
+                object: target,
+                name,
+                value,
+            } => object(
+                "Set",
+                &[
+                    ("object", target.to_json()),
+                    ("name", token(name)),
+                    ("value", value.to_json()),
+                ],
+            ),
+            Expression::Super { method, .. } => object("Super", &[("method", token(method))]),
+            Expression::Ternary {
+                condition,
+                then_expr,
+                else_expr,
+            } => object(
+                "Ternary",
+                &[
+                    ("condition", condition.to_json()),
+                    ("then_expr", then_expr.to_json()),
+                    ("else_expr", else_expr.to_json()),
+                ],
+            ),
+            Expression::This { .. } => object("This", &[]),
+            Expression::Unary { operator, right } => object(
+                "Unary",
+                &[("operator", token(operator)), ("right", right.to_json())],
+            ),
+            Expression::Variable { name } => object("Variable", &[("name", token(name))]),
+        }
+    }
+}
+
+impl ToJson for Statement {
+    fn to_json(&self) -> String {
+        match self {
+            Statement::Block(statements) => object("Block", &[("statements", array(statements))]),
+            Statement::Class {
+                name,
+                superclass,
+                methods,
+            } => object(
+                "Class",
+                &[
+                    ("name", token(name)),
+                    ("superclass", optional(superclass.as_ref())),
+                    ("methods", array(methods)),
+                ],
+            ),
+            Statement::Expression(expression) => {
+                object("Expression", &[("expression", expression.to_json())])
+            }
+            Statement::Function(declaration) => declaration.to_json(),
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => object(
+                "If",
+                &[
+                    ("condition", condition.to_json()),
+                    ("then_branch", then_branch.to_json()),
+                    ("else_branch", optional(else_branch.as_deref())),
+                ],
+            ),
+            Statement::Print { expressions, .. } => {
+                object("Print", &[("expressions", array(expressions))])
+            }
+            Statement::Return { value, .. } => {
+                object("Return", &[("value", optional(value.as_ref()))])
+            }
+            Statement::Var { name, initializer } => object(
+                "Var",
+                &[
+                    ("name", token(name)),
+                    ("initializer", optional(initializer.as_ref())),
+                ],
+            ),
+            Statement::While {
+                condition, body, ..
+            } => object(
+                "While",
+                &[("condition", condition.to_json()), ("body", body.to_json())],
+            ),
+        }
+    }
+}
+
+impl ToJson for FunctionDeclaration {
+    fn to_json(&self) -> String {
+        object(
+            "Function",
+            &[
+                ("name", token(self.declared_name())),
+                ("params", params(&self.params)),
+                ("body", array(&self.body)),
+            ],
+        )
+    }
+}
+
+/// Build an object tagged with `type_name` from already serialised field values
+fn object(type_name: &str, fields: &[(&str, String)]) -> String {
+    let mut builder = format!("{{\"type\":{}", string(type_name));
+
+    for (key, value) in fields {
+        builder.push_str(&format!(",{}:{}", string(key), value));
+    }
+
+    builder.push('}');
+    builder
+}
+
+fn array<T: ToJson>(items: &[T]) -> String {
+    let items = items.iter().map(ToJson::to_json).collect::<Vec<_>>();
+    format!("[{}]", items.join(","))
+}
+
+fn optional<T: ToJson>(item: Option<&T>) -> String {
+    item.map_or_else(|| "null".to_string(), ToJson::to_json)
+}
+
+fn params(params: &[Token]) -> String {
+    let params = params.iter().map(token).collect::<Vec<_>>().join(",");
+    format!("[{params}]")
+}
+
+fn token(token: &Token) -> String {
+    string(&token.lexeme)
+}
+
+fn literal(literal: &Literal) -> String {
+    match literal {
+        Literal::String(str) => string(str),
+        // JSON has no representation of infinity, which very large number literals overflow to
+        Literal::Number(num) if num.is_finite() => num.to_string(),
+        Literal::Number(_) => "null".to_string(),
+        Literal::Boolean(bool) => bool.to_string(),
+        Literal::None => "null".to_string(),
+    }
+}
+
+/// Quote and escape text as a JSON string
+pub(crate) fn string(str: &str) -> String {
+    let mut builder = String::from('"');
+
+    for c in str.chars() {
+        match c {
+            '"' => builder.push_str("\\\""),
+            '\\' => builder.push_str("\\\\"),
+            '\n' => builder.push_str("\\n"),
+            '\r' => builder.push_str("\\r"),
+            '\t' => builder.push_str("\\t"),
+            c if (c as u32) < 0x20 => builder.push_str(&format!("\\u{:04x}", c as u32)),
+            c => builder.push(c),
+        }
+    }
+
+    builder.push('"');
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ToJson;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn json_of(source: &str) -> Vec<String> {
+        let tokens = Scanner::new(source.to_string())
+            .scan_tokens()
+            .expect("source should scan");
+        let statements = Parser::new(tokens).parse().unwrap_or_default();
+        statements.iter().map(ToJson::to_json).collect()
+    }
+
+    #[test]
+    fn serialises_expressions_by_variant() {
+        assert_eq!(
+            json_of("-a + 1.5;"),
+            [concat!(
+                r#"{"type":"Expression","expression":{"type":"Binary","operator":"+","#,
+                r#""left":{"type":"Unary","operator":"-","right":{"type":"Variable","name":"a"}},"#,
+                r#""right":{"type":"Literal","value":1.5}}}"#
+            )]
+        );
+    }
+
+    #[test]
+    fn serialises_missing_fields_and_literals() {
+        assert_eq!(
+            json_of(r#"var x; print "a\"b", nil, true;"#),
+            [
+                r#"{"type":"Var","name":"x","initializer":null}"#,
+                concat!(
+                    r#"{"type":"Print","expressions":[{"type":"Literal","value":"a\"b"},"#,
+                    r#"{"type":"Literal","value":null},{"type":"Literal","value":true}]}"#
+                ),
+            ]
+        );
+    }
+}
@@ -1,5 +1,10 @@
 mod ast_printer;
 mod generic_scanner;
+mod json;
+mod source_printer;
 
-pub use ast_printer::AstPrinter;
+pub use ast_printer::{format_statement, AstPrinter};
 pub use generic_scanner::GenericScanner;
+pub(crate) use json::string as json_string;
+pub use json::ToJson;
+pub use source_printer::ToSource;
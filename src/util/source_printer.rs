@@ -0,0 +1,548 @@
+//! Regeneration of Lox source from syntax trees, in a canonical layout: one statement per line,
+//! blocks indented by four spaces and single spaces around binary operators.
+//!
+//! The parser desugars some constructs, so `for` loops come back as `while` loops and `x += 1`
+//! as `x = x + 1`. The output always parses back to an equivalent tree, with parentheses added
+//! wherever the tree would otherwise be read with a different precedence.
+
+use crate::expression::{Expression, Visitor};
+use crate::statement::{FunctionDeclaration, Statement};
+use crate::token::{Literal, Token, TokenType};
+
+const INDENT: &str = "    ";
+
+pub trait ToSource {
+    fn to_source(&self) -> String;
+}
+
+impl ToSource for Expression {
+    fn to_source(&self) -> String {
+        source(self, 0)
+    }
+}
+
+impl ToSource for Statement {
+    fn to_source(&self) -> String {
+        let mut builder = String::new();
+        write_statement(&mut builder, self, 0);
+        builder
+    }
+}
+
+impl ToSource for [Statement] {
+    fn to_source(&self) -> String {
+        let mut builder = String::new();
+        for statement in self {
+            write_statement(&mut builder, statement, 0);
+        }
+        builder
+    }
+}
+
+/// Format an expression within a statement at the given depth of indentation, which the bodies
+/// of any anonymous functions inside it are indented from
+fn source(expression: &Expression, depth: usize) -> String {
+    expression.accept(&mut SourcePrinter { depth })
+}
+
+/// Append a statement at the given depth of indentation, ending with a newline
+fn write_statement(builder: &mut String, statement: &Statement, depth: usize) {
+    builder.push_str(&INDENT.repeat(depth));
+
+    match statement {
+        Statement::Block(statements) => write_block(builder, statements, depth),
+        Statement::Class {
+            name,
+            superclass,
+            methods,
+        } => {
+            builder.push_str(&format!("class {}", name.lexeme));
+            if let Some(superclass) = superclass {
+                builder.push_str(&format!(" < {}", source(superclass, depth)));
+            }
+
+            if methods.is_empty() {
+                builder.push_str(" {}");
+            } else {
+                builder.push_str(" {\n");
+                for method in methods {
+                    builder.push_str(&INDENT.repeat(depth + 1));
+                    write_function(builder, method, depth + 1);
+                    builder.push('\n');
+                }
+                builder.push_str(&INDENT.repeat(depth));
+                builder.push('}');
+            }
+        }
+        Statement::Expression(expression) => {
+            builder.push_str(&format!("{};", source(expression, depth)))
+        }
+        Statement::Function(declaration) => {
+            builder.push_str("fun ");
+            write_function(builder, declaration, depth);
+        }
+        Statement::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            write_if(
+                builder,
+                condition,
+                then_branch,
+                else_branch.as_deref(),
+                depth,
+            );
+            return;
+        }
+        Statement::Print { expressions, .. } => {
+            // Commas separate the values, so a comma expression among them needs parentheses
+            let values = SourcePrinter { depth }.list(expressions, ", ");
+            builder.push_str(&format!("print {values};"))
+        }
+        Statement::Return { value, .. } => match value {
+            Some(value) => builder.push_str(&format!("return {};", source(value, depth))),
+            None => builder.push_str("return;"),
+        },
+        Statement::Var { name, initializer } => match initializer {
+            Some(initializer) => builder.push_str(&format!(
+                "var {} = {};",
+                name.lexeme,
+                source(initializer, depth)
+            )),
+            None => builder.push_str(&format!("var {};", name.lexeme)),
+        },
+        Statement::While {
+            condition, body, ..
+        } => {
+            builder.push_str(&format!("while ({})", source(condition, depth)));
+            if !write_branch(builder, body, depth) {
+                return;
+            }
+        }
+    }
+
+    builder.push('\n');
+}
+
+/// Append the body of an `if` or loop. A block opens on the same line and is left for the caller
+/// to end, returning `true`, while anything else is indented on the lines after.
+fn write_branch(builder: &mut String, branch: &Statement, depth: usize) -> bool {
+    if let Statement::Block(statements) = branch {
+        builder.push(' ');
+        write_block(builder, statements, depth);
+        true
+    } else {
+        builder.push('\n');
+        write_statement(builder, branch, depth + 1);
+        false
+    }
+}
+
+/// Append an `if` statement from its keyword onwards, ending with a newline. Chains of `else if`
+/// are kept at the same depth rather than nesting further each time.
+fn write_if(
+    builder: &mut String,
+    condition: &Expression,
+    then_branch: &Statement,
+    else_branch: Option<&Statement>,
+    depth: usize,
+) {
+    builder.push_str(&format!("if ({})", source(condition, depth)));
+    let is_line_open = write_branch(builder, then_branch, depth);
+
+    let Some(else_branch) = else_branch else {
+        if is_line_open {
+            builder.push('\n');
+        }
+        return;
+    };
+
+    if is_line_open {
+        builder.push_str(" else");
+    } else {
+        builder.push_str(&INDENT.repeat(depth));
+        builder.push_str("else");
+    }
+
+    if let Statement::If {
+        condition,
+        then_branch,
+        else_branch,
+    } = else_branch
+    {
+        builder.push(' ');
+        write_if(
+            builder,
+            condition,
+            then_branch,
+            else_branch.as_deref(),
+            depth,
+        );
+    } else if write_branch(builder, else_branch, depth) {
+        builder.push('\n');
+    }
+}
+
+/// Append braces around the given statements, without indenting the opening brace or ending the
+/// line after the closing one
+fn write_block(builder: &mut String, statements: &[Statement], depth: usize) {
+    if statements.is_empty() {
+        builder.push_str("{}");
+        return;
+    }
+
+    builder.push_str("{\n");
+    for statement in statements {
+        write_statement(builder, statement, depth + 1);
+    }
+    builder.push_str(&INDENT.repeat(depth));
+    builder.push('}');
+}
+
+/// Append a function's name, parameters and body, without any `fun` keyword
+fn write_function(builder: &mut String, declaration: &FunctionDeclaration, depth: usize) {
+    builder.push_str(&format!(
+        "{}({}) ",
+        declaration.declared_name().lexeme,
+        params(declaration)
+    ));
+    write_block(builder, &declaration.body, depth);
+}
+
+fn params(declaration: &FunctionDeclaration) -> String {
+    declaration
+        .params
+        .iter()
+        .map(|param| param.lexeme.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// How tightly each kind of expression binds, from loosest to tightest, following the levels of
+/// the parser
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Comma,
+    Assignment,
+    Ternary,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Power,
+    Call,
+}
+
+impl Precedence {
+    fn of(expression: &Expression) -> Self {
+        match expression {
+            Expression::Comma { .. } => Precedence::Comma,
+            Expression::Assign { .. } | Expression::Set { .. } | Expression::IndexSet { .. } => {
+                Precedence::Assignment
+            }
+            Expression::Ternary { .. } => Precedence::Ternary,
+            Expression::Binary { operator, .. } | Expression::Logical { operator, .. } => {
+                Self::of_operator(operator)
+            }
+            Expression::Unary { .. } => Precedence::Unary,
+            _ => Precedence::Call,
+        }
+    }
+
+    fn of_operator(operator: &Token) -> Self {
+        match operator.token_type {
+            TokenType::Or => Precedence::Or,
+            TokenType::And => Precedence::And,
+            TokenType::BangEqual | TokenType::EqualEqual => Precedence::Equality,
+            TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Less
+            | TokenType::LessEqual => Precedence::Comparison,
+            TokenType::Minus | TokenType::Plus => Precedence::Term,
+            TokenType::StarStar => Precedence::Power,
+            _ => Precedence::Factor,
+        }
+    }
+}
+
+struct SourcePrinter {
+    depth: usize,
+}
+
+impl SourcePrinter {
+    /// Format an operand, in parentheses if it binds more loosely than its position requires
+    fn operand(&mut self, expression: &Expression, minimum: Precedence) -> String {
+        let source = expression.accept(self);
+        if Precedence::of(expression) < minimum {
+            format!("({source})")
+        } else {
+            source
+        }
+    }
+
+    fn list(&mut self, expressions: &[Expression], separator: &str) -> String {
+        expressions
+            .iter()
+            .map(|expression| self.operand(expression, Precedence::Assignment))
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+
+    fn infix(&mut self, left: &Expression, operator: &Token, right: &Expression) -> String {
+        let precedence = Precedence::of_operator(operator);
+
+        // Exponents group to the right and take a unary exponent, everything else groups left
+        let (left, right) = if operator.token_type == TokenType::StarStar {
+            (
+                self.operand(left, Precedence::Call),
+                self.operand(right, Precedence::Unary),
+            )
+        } else {
+            (
+                self.operand(left, precedence),
+                self.operand(right, next(precedence)),
+            )
+        };
+        format!("{} {} {}", left, operator.lexeme, right)
+    }
+}
+
+/// The level binding one step more tightly than the given one
+fn next(precedence: Precedence) -> Precedence {
+    match precedence {
+        Precedence::Comma => Precedence::Assignment,
+        Precedence::Assignment => Precedence::Ternary,
+        Precedence::Ternary => Precedence::Or,
+        Precedence::Or => Precedence::And,
+        Precedence::And => Precedence::Equality,
+        Precedence::Equality => Precedence::Comparison,
+        Precedence::Comparison => Precedence::Term,
+        Precedence::Term => Precedence::Factor,
+        Precedence::Factor => Precedence::Unary,
+        Precedence::Unary => Precedence::Power,
+        Precedence::Power | Precedence::Call => Precedence::Call,
+    }
+}
+
+impl Visitor<String> for SourcePrinter {
+    fn visit_assign(&mut self, name: &Token, value: &Expression) -> String {
+        format!(
+            "{} = {}",
+            name.lexeme,
+            self.operand(value, Precedence::Assignment)
+        )
+    }
+
+    fn visit_binary(&mut self, left: &Expression, operator: &Token, right: &Expression) -> String {
+        self.infix(left, operator, right)
+    }
+
+    fn visit_call(&mut self, callee: &Expression, _: &Token, arguments: &[Expression]) -> String {
+        format!(
+            "{}({})",
+            self.operand(callee, Precedence::Call),
+            self.list(arguments, ", ")
+        )
+    }
+
+    fn visit_comma(&mut self, expressions: &[Expression]) -> String {
+        self.list(expressions, ", ")
+    }
+
+    fn visit_get(&mut self, object: &Expression, name: &Token) -> String {
+        format!("{}.{}", self.operand(object, Precedence::Call), name.lexeme)
+    }
+
+    fn visit_grouping(&mut self, expression: &Expression) -> String {
+        format!("({})", expression.accept(self))
+    }
+
+    fn visit_index(&mut self, collection: &Expression, _: &Token, index: &Expression) -> String {
+        format!(
+            "{}[{}]",
+            self.operand(collection, Precedence::Call),
+            index.accept(self)
+        )
+    }
+
+    fn visit_index_set(
+        &mut self,
+        collection: &Expression,
+        _: &Token,
+        index: &Expression,
+        value: &Expression,
+    ) -> String {
+        format!(
+            "{}[{}] = {}",
+            self.operand(collection, Precedence::Call),
+            index.accept(self),
+            self.operand(value, Precedence::Assignment)
+        )
+    }
+
+    fn visit_lambda(&mut self, _: &Token, declaration: &FunctionDeclaration) -> String {
+        let mut builder = format!("fun ({}) ", params(declaration));
+        write_block(&mut builder, &declaration.body, self.depth);
+        builder
+    }
+
+    fn visit_list_literal(&mut self, elements: &[Expression]) -> String {
+        format!("[{}]", self.list(elements, ", "))
+    }
+
+    fn visit_literal(&mut self, value: &Literal) -> String {
+        match value {
+            Literal::String(str) => quote(str),
+            Literal::Number(num) => number(*num),
+            Literal::Boolean(bool) => bool.to_string(),
+            Literal::None => "nil".to_string(),
+        }
+    }
+
+    fn visit_logical(&mut self, left: &Expression, operator: &Token, right: &Expression) -> String {
+        self.infix(left, operator, right)
+    }
+
+    fn visit_set(&mut self, object: &Expression, name: &Token, value: &Expression) -> String {
+        format!(
+            "{}.{} = {}",
+            self.operand(object, Precedence::Call),
+            name.lexeme,
+            self.operand(value, Precedence::Assignment)
+        )
+    }
+
+    fn visit_super(&mut self, _: &Token, method: &Token) -> String {
+        format!("super.{}", method.lexeme)
+    }
+
+    fn visit_ternary(
+        &mut self,
+        condition: &Expression,
+        then_expr: &Expression,
+        else_expr: &Expression,
+    ) -> String {
+        format!(
+            "{} ? {} : {}",
+            self.operand(condition, Precedence::Or),
+            then_expr.accept(self),
+            self.operand(else_expr, Precedence::Ternary)
+        )
+    }
+
+    fn visit_this(&mut self, _: &Token) -> String {
+        "this".to_string()
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expression) -> String {
+        let right = self.operand(right, Precedence::Unary);
+
+        // `- -x` must not run together into the `--` operator
+        if right.starts_with(&operator.lexeme) && operator.token_type == TokenType::Minus {
+            format!("{} {}", operator.lexeme, right)
+        } else {
+            format!("{}{}", operator.lexeme, right)
+        }
+    }
+
+    fn visit_variable(&mut self, name: &Token) -> String {
+        name.lexeme.clone()
+    }
+}
+
+/// Write a number literal in as few characters as still read back as the same number, using an
+/// exponent only where it makes the literal shorter
+fn number(num: f64) -> String {
+    let plain = num.to_string();
+    let exponent = format!("{num:e}");
+    if exponent.len() < plain.len() {
+        exponent
+    } else {
+        plain
+    }
+}
+
+/// Write a string literal with the escape sequences the scanner understands
+fn quote(str: &str) -> String {
+    let mut builder = String::from('"');
+
+    for c in str.chars() {
+        match c {
+            '"' => builder.push_str("\\\""),
+            '\\' => builder.push_str("\\\\"),
+            '\n' => builder.push_str("\\n"),
+            '\r' => builder.push_str("\\r"),
+            '\t' => builder.push_str("\\t"),
+            '\0' => builder.push_str("\\0"),
+            c => builder.push(c),
+        }
+    }
+
+    builder.push('"');
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ToSource;
+    use crate::{parse_source, Statement};
+
+    fn parse(source: &str) -> Vec<Statement> {
+        match parse_source(source) {
+            Ok(statements) => statements,
+            Err(diagnostics) => panic!("Failed to parse: {:?}", diagnostics),
+        }
+    }
+
+    /// Syntax trees in prefix notation, to compare them
+    fn trees(statements: &[Statement]) -> Vec<String> {
+        statements.iter().map(Statement::to_string).collect()
+    }
+
+    #[test]
+    fn formats_source_which_parses_to_the_same_tree() {
+        let statements = parse("{var x=1;print x+2;}");
+        let formatted = statements.to_source();
+        assert_eq!(formatted, "{\n    var x = 1;\n    print x + 2;\n}\n");
+
+        let reparsed = parse(&formatted);
+        assert_eq!(trees(&reparsed), trees(&statements));
+        assert_eq!(reparsed.to_source(), formatted);
+    }
+
+    #[test]
+    fn formats_nested_declarations() {
+        let source = "fun f(a,b){while(a<b)a=a+1;{return b;}}class C<D{m(){this.x=-1;}}";
+        let statements = parse(source);
+        let formatted = statements.to_source();
+        assert_eq!(trees(&parse(&formatted)), trees(&statements));
+        assert_eq!(
+            formatted,
+            concat!(
+                "fun f(a, b) {\n",
+                "    while (a < b)\n",
+                "        a = a + 1;\n",
+                "    {\n",
+                "        return b;\n",
+                "    }\n",
+                "}\n",
+                "class C < D {\n",
+                "    m() {\n",
+                "        this.x = -1;\n",
+                "    }\n",
+                "}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn formats_numbers_as_briefly_as_they_read_back() {
+        let formatted = parse("print 4e18, 1.5e-10, 123456, 0.25, 1e300;").to_source();
+        assert_eq!(formatted, "print 4e18, 1.5e-10, 123456, 0.25, 1e300;\n");
+        assert_eq!(parse(&formatted).to_source(), formatted);
+    }
+}
@@ -0,0 +1,230 @@
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::callable::LoxCallable;
+use crate::class::{LoxClass, LoxInstance};
+use crate::token::Literal;
+
+#[derive(Clone)]
+pub enum Value {
+    /// Strings are immutable, so they are shared rather than copied when the value is cloned
+    String(Rc<str>),
+    Number(f64),
+    Boolean(bool),
+    Callable(Rc<dyn LoxCallable>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Nil,
+}
+
+impl Value {
+    /// Only `nil` and `false` are falsey, everything else (including `0` and `""`) is truthy
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::String(_)
+            | Value::Number(_)
+            | Value::Callable(_)
+            | Value::Class(_)
+            | Value::Instance(_)
+            | Value::List(_) => true,
+            Value::Boolean(bool) => *bool,
+            Value::Nil => false,
+        }
+    }
+
+    /// The number held by this value, or `None` if it isn't a number
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(num) => Some(*num),
+            _ => None,
+        }
+    }
+
+    /// Whether this value is a number with no fractional part
+    pub fn is_integer(&self) -> bool {
+        self.as_number()
+            .is_some_and(|num| num.is_finite() && num.fract() == 0.0)
+    }
+
+    /// Name of this value's type, as returned by the `type()` native function
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Number(_) => "number",
+            Value::Boolean(_) => "boolean",
+            Value::Callable(_) => "function",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::List(_) => "list",
+            Value::Nil => "nil",
+        }
+    }
+}
+
+impl From<Literal> for Value {
+    fn from(literal: Literal) -> Self {
+        match literal {
+            Literal::String(str) => Value::String(str.into()),
+            Literal::Number(num) => Value::Number(num),
+            Literal::Boolean(bool) => Value::Boolean(bool),
+            Literal::None => Value::Nil,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(bool: bool) -> Self {
+        Value::Boolean(bool)
+    }
+}
+
+/// Equality as tested by Lox's `==` and `!=`. Values of different types are never equal, as there
+/// is no coercion, so `1 == "1"` and `true == 1` are false while `nil == nil` is true. Numbers
+/// compare by IEEE 754 rules, so `NaN` is not equal to anything, including itself.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::String(left), Value::String(right)) => left == right,
+            (Value::Number(left), Value::Number(right)) => left == right,
+            (Value::Boolean(left), Value::Boolean(right)) => left == right,
+            // Callables, classes, instances and lists are only equal to themselves
+            (Value::Callable(left), Value::Callable(right)) => Rc::ptr_eq(left, right),
+            (Value::Class(left), Value::Class(right)) => Rc::ptr_eq(left, right),
+            (Value::Instance(left), Value::Instance(right)) => Rc::ptr_eq(left, right),
+            (Value::List(left), Value::List(right)) => Rc::ptr_eq(left, right),
+            (Value::Nil, Value::Nil) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::String(str) => str.to_string(),
+                Self::Number(num) => format_number(*num),
+                Self::Boolean(bool) => bool.to_string(),
+                Self::Callable(function) => match function.name() {
+                    Some(name) => format!("<fn {name}>"),
+                    None => "<fn>".to_string(),
+                },
+                Self::Class(class) => class.name.clone(),
+                Self::Instance(instance) => format!("{} instance", instance.borrow().class.name),
+                Self::List(elements) => {
+                    let elements = elements
+                        .borrow()
+                        .iter()
+                        .map(Value::to_string)
+                        .collect::<Vec<_>>();
+                    format!("[{}]", elements.join(", "))
+                }
+                Self::Nil => "nil".to_string(),
+            }
+        )
+    }
+}
+
+/// Format a number the way jlox prints it, which is Java's `Double.toString` without a trailing
+/// `.0`. Magnitudes from 10^-3 up to but not including 10^7 print in full, and any others use
+/// an exponent with at least one decimal, such as `1.0E21` or `1.5E-5`.
+fn format_number(num: f64) -> String {
+    if num.is_nan() {
+        "NaN".to_string()
+    } else if num.is_infinite() {
+        let sign = if num < 0.0 { "-" } else { "" };
+        format!("{sign}Infinity")
+    } else if num != 0.0 && (num.abs() >= 1e7 || num.abs() < 1e-3) {
+        let formatted = format!("{num:e}");
+        let (mantissa, exponent) = formatted
+            .split_once('e')
+            .expect("Exponent notation always has an exponent");
+        if mantissa.contains('.') {
+            format!("{mantissa}E{exponent}")
+        } else {
+            format!("{mantissa}.0E{exponent}")
+        }
+    } else {
+        num.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Value;
+    use crate::test_util::output_of;
+
+    #[test]
+    fn only_nil_and_false_are_falsey() {
+        assert!(Value::Number(0.0).is_truthy());
+        assert!(Value::String("".into()).is_truthy());
+        assert!(Value::Boolean(true).is_truthy());
+        assert!(!Value::Boolean(false).is_truthy());
+        assert!(!Value::Nil.is_truthy());
+    }
+
+    #[test]
+    fn displays_whole_numbers_without_decimals() {
+        let display = |num| Value::Number(num).to_string();
+        assert_eq!(display(3.5), "3.5");
+        assert_eq!(display(4.0), "4");
+        assert_eq!(display(-0.0), "-0");
+        assert_eq!(display(1234567.0), "1234567");
+        assert_eq!(display(0.001), "0.001");
+        assert_eq!(display(1e7), "1.0E7");
+        assert_eq!(display(1e21), "1.0E21");
+        assert_eq!(display(-1.5e300), "-1.5E300");
+        assert_eq!(display(0.00015), "1.5E-4");
+        assert_eq!(display(f64::INFINITY), "Infinity");
+    }
+
+    #[test]
+    fn reads_numbers_only_from_number_values() {
+        assert_eq!(Value::Number(2.5).as_number(), Some(2.5));
+        assert_eq!(Value::String("2.5".into()).as_number(), None);
+        assert_eq!(Value::Nil.as_number(), None);
+        assert_eq!(Value::from(true).as_number(), None);
+    }
+
+    #[test]
+    fn detects_whole_numbers() {
+        assert!(Value::Number(4.0).is_integer());
+        assert!(Value::Number(-3.0).is_integer());
+        assert!(!Value::Number(3.5).is_integer());
+        assert!(!Value::Number(f64::INFINITY).is_integer());
+        assert!(!Value::Number(f64::NAN).is_integer());
+        assert!(!Value::String("4".into()).is_integer());
+        assert!(!Value::Nil.is_integer());
+    }
+
+    #[test]
+    fn compares_strings_by_contents() {
+        let shared = Value::String("ab".into());
+        assert!(shared.clone() == shared);
+        assert!(Value::String(format!("a{}", "b").into()) == shared);
+        assert!(Value::String("ba".into()) != shared);
+    }
+
+    #[test]
+    fn concatenation_builds_a_new_string() {
+        let source = r#"var a = "x"; var b = a + "y"; print a; print b; print a + "y" == b;"#;
+        assert_eq!(output_of(source), "x\nxy\ntrue\n");
+    }
+
+    #[test]
+    fn compares_without_coercion() {
+        assert!(Value::Nil == Value::Nil);
+        assert!(Value::Number(1.0) != Value::Nil);
+        assert!(Value::Number(1.0) != Value::String("1".into()));
+        assert!(Value::Boolean(true) != Value::Number(1.0));
+        assert!(Value::Number(f64::NAN) != Value::Number(f64::NAN));
+        assert_eq!(
+            output_of(r#"print nil == nil; print 1 == "1"; print true == 1; print 1 == nil;"#),
+            "true\nfalse\nfalse\nfalse\n"
+        );
+    }
+}